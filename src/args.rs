@@ -1,4 +1,7 @@
+use crate::settings::*;
+use crate::sourcecode::ParseOptions;
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
 #[derive(StructOpt)]
 pub struct Args {
@@ -10,7 +13,141 @@ pub struct Args {
     #[structopt(short, long)]
     pub interactive: bool,
 
-    /// path to brainfuck source code file
-    #[structopt(parse(from_os_str), default_value(""))]
-    pub input_path: PathBuf,
+    /// debug the program step by step, with breakpoints and memory inspection
+    #[structopt(long, conflicts_with = "interactive")]
+    pub debug: bool,
+
+    /// feed the contents of this file to the program's `,` commands instead of stdin
+    #[structopt(long, parse(from_os_str), conflicts_with = "input")]
+    pub input_file: Option<PathBuf>,
+
+    /// feed this string to the program's `,` commands instead of stdin
+    #[structopt(long)]
+    pub input: Option<String>,
+
+    /// treat `#` as a command that prints the head position and the surrounding cells to stderr
+    #[structopt(long)]
+    pub debug_hash: bool,
+
+    /// treat everything behind the first `!` in the source file as the program's input
+    #[structopt(long)]
+    pub embedded_input: bool,
+
+    /// never ask for input, read stdin silently even if it is a terminal (ignored in the interactive environment)
+    #[structopt(long)]
+    pub no_prompt: bool,
+
+    /// value of a cell after `,` reached the end of the input
+    #[structopt(long, default_value = "zero", possible_values = &EofPolicy::VARIANTS)]
+    pub eof: EofPolicy,
+
+    /// stop the program with an error after executing this many instructions
+    #[structopt(long)]
+    pub max_steps: Option<u64>,
+
+    /// stop the program with an error once it tries to write more than this many bytes
+    #[structopt(long)]
+    pub max_output: Option<u64>,
+
+    /// stop the program with an error once the infinite memory band would grow beyond this many cells
+    #[structopt(long)]
+    pub max_memory: Option<usize>,
+
+    /// stop the program with an error after running for this many seconds
+    #[structopt(long, parse(try_from_str = parse_seconds))]
+    pub timeout: Option<Duration>,
+
+    /// record every executed instruction as JSON Lines in this file
+    #[structopt(long, parse(from_os_str))]
+    pub trace: Option<PathBuf>,
+
+    /// only record every n-th instruction in the trace, counting only those kept by --trace-only
+    #[structopt(long, default_value = "1")]
+    pub trace_every: u64,
+
+    /// only record these comma separated kinds of instructions in the trace: move, add, print, read, loop, debug
+    #[structopt(long)]
+    pub trace_only: Option<String>,
+
+    /// print the hottest loops and instructions to stderr after the run
+    #[structopt(long)]
+    pub profile: bool,
+
+    /// print statistics like the executed instructions and the wall time to stderr after the run
+    #[structopt(long)]
+    pub stats: bool,
+
+    /// write the source code annotated with the executions of every line to this file
+    #[structopt(long, parse(from_os_str))]
+    pub coverage: Option<PathBuf>,
+
+    /// write the line coverage as LCOV tracefile to this file
+    #[structopt(long, parse(from_os_str))]
+    pub lcov: Option<PathBuf>,
+
+    /// restore the memory band from a snapshot file before running
+    #[structopt(long, parse(from_os_str))]
+    pub load_memory: Option<PathBuf>,
+
+    /// save the memory band to a snapshot file after running, or when leaving the interactive environment
+    #[structopt(long, parse(from_os_str))]
+    pub save_memory: Option<PathBuf>,
+
+    /// run this brainfuck code instead of reading it from a file
+    #[structopt(short, long, conflicts_with = "input-path")]
+    pub eval: Option<String>,
+
+    /// path to brainfuck source code file, use `-` to read the code from stdin
+    // not required by clap, whose message would not mention the alternatives, see `read_source`
+    #[structopt(parse(from_os_str))]
+    pub input_path: Option<PathBuf>,
+}
+
+impl Args {
+    /// The parser extensions requested on the command line
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            debug_hash: self.debug_hash,
+        }
+    }
+
+    /// The execution settings requested on the command line
+    pub fn settings(&self) -> Settings {
+        Settings {
+            eof: self.eof,
+            max_steps: self.max_steps,
+            max_output: self.max_output,
+            max_memory: self.max_memory,
+            timeout: self.timeout,
+            cancellation: None,
+        }
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let secs = s.parse::<f64>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_relative_path() {
+        for path in ["hello.b", "debug.b", "help"] {
+            let args = Args::from_iter_safe(&["rsbrainfuck", path]).unwrap();
+            assert_eq!(args.input_path, Some(PathBuf::from(path)));
+            assert!(!args.debug);
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let args = Args::from_iter_safe(&["rsbrainfuck", "--debug", "hello.b"]).unwrap();
+        assert!(args.debug);
+        assert_eq!(args.input_path, Some(PathBuf::from("hello.b")));
+
+        assert!(Args::from_iter_safe(&["rsbrainfuck", "--debug", "-i"]).is_err());
+    }
 }
@@ -0,0 +1,198 @@
+use super::memoryband::MemoryBand;
+use super::observer::{Instruction, Observer};
+use super::sourcecode::{BfCommand, LineIndex, SourceCode, Span};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// Counts how often every command of a program was executed.
+///
+/// Loops are covered by their brackets, which count the checks of the loop condition.
+pub struct Coverage {
+    lines: LineIndex,
+    counts: BTreeMap<Span, u64>,
+}
+
+impl Coverage {
+    /// Creates a coverage of `code`, which was parsed from `source`, in which no command was
+    /// executed yet
+    pub fn new(source: &str, code: &SourceCode) -> Coverage {
+        let mut counts = BTreeMap::new();
+        collect_spans(code, &mut counts);
+        Coverage {
+            lines: LineIndex::new(source),
+            counts,
+        }
+    }
+
+    /// Executions of every command, ordered by their position in the source code
+    pub fn counts(&self) -> &BTreeMap<Span, u64> {
+        &self.counts
+    }
+
+    /// Executions of every line of the source code, the most often executed command on a line
+    /// counts for the line. Lines without commands are `None`.
+    pub fn line_counts(&self) -> Vec<Option<u64>> {
+        let mut lines = vec![None; self.lines.source().lines().count().max(1)];
+        for (span, count) in self.counts.iter() {
+            let entry = &mut lines[self.lines.line(span.start) - 1];
+            *entry = Some(entry.unwrap_or(0).max(*count));
+        }
+        lines
+    }
+
+    /// Line and column, both counted from 1, of every command that never ran
+    pub fn unexecuted(&self) -> Vec<(usize, usize)> {
+        self.counts
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(span, _)| self.lines.line_col(span.start))
+            .collect()
+    }
+
+    /// Formats the source code with the executions in front of every line like gcov does:
+    /// `-` marks lines without commands and `#####` lines with commands that never ran. Below a
+    /// line on which only some commands ran, a `^` marks every command that never ran.
+    pub fn listing(&self) -> String {
+        let mut listing = String::new();
+        let lines = self.line_counts();
+        let mut unexecuted = self.unexecuted().into_iter().peekable();
+        for (i, text) in self.lines.source().lines().enumerate() {
+            let count = match lines[i] {
+                Some(0) => String::from("#####"),
+                Some(count) => count.to_string(),
+                None => String::from("-"),
+            };
+            let _ = writeln!(listing, "{:>9}:{:>5}:{}", count, i + 1, text);
+            let mut columns = Vec::new();
+            while let Some((_, column)) = unexecuted.next_if(|(line, _)| *line == i + 1) {
+                columns.push(column);
+            }
+            if lines[i] != Some(0) && !columns.is_empty() {
+                let _ = writeln!(listing, "{:>9}:{:>5}:{}", "", "", markers(text, &columns));
+            }
+        }
+        let executed = self.counts.values().filter(|count| **count > 0).count();
+        let _ = writeln!(listing, "{} of {} commands executed", executed, self.counts.len());
+        listing
+    }
+
+    /// Writes the line coverage in LCOV's tracefile format, naming the source file `path`
+    pub fn write_lcov<W: Write>(&self, out: &mut W, path: &str) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", path)?;
+        let (mut found, mut hit) = (0, 0);
+        for (i, count) in self.line_counts().into_iter().enumerate() {
+            if let Some(count) = count {
+                writeln!(out, "DA:{},{}", i + 1, count)?;
+                found += 1;
+                if count > 0 {
+                    hit += 1;
+                }
+            }
+        }
+        writeln!(out, "LF:{}", found)?;
+        writeln!(out, "LH:{}", hit)?;
+        writeln!(out, "end_of_record")
+    }
+}
+
+/// A line with a `^` below every given column of `text`, keeping tabs so that the markers line
+/// up with the commands
+fn markers(text: &str, columns: &[usize]) -> String {
+    let last = columns.iter().copied().max().unwrap_or(0);
+    text.chars()
+        .chain(std::iter::repeat(' '))
+        .take(last)
+        .enumerate()
+        .map(|(i, c)| match c {
+            _ if columns.contains(&(i + 1)) => '^',
+            '\t' => '\t',
+            _ => ' ',
+        })
+        .collect()
+}
+
+impl Observer for Coverage {
+    fn instruction<M: MemoryBand>(&mut self, _: Instruction, span: Span, _: &M, _: u8) {
+        *self.counts.entry(span).or_insert(0) += 1;
+    }
+}
+
+/// Adds the span of every command in `code` to `counts`, using the spans of the brackets for
+/// loops like the observer events do
+fn collect_spans(code: &SourceCode, counts: &mut BTreeMap<Span, u64>) {
+    for (command, span) in code.commands.iter().zip(code.spans.iter()) {
+        match command {
+            BfCommand::Loop(body) => {
+                counts.insert(Span { start: span.start, end: span.start + 1 }, 0);
+                counts.insert(Span { start: span.end - 1, end: span.end }, 0);
+                collect_spans(body, counts);
+            }
+            _ => {
+                counts.insert(*span, 0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::InfiniteMemoryBand;
+    use crate::observer::run_observed;
+
+    fn cover(source: &str) -> Coverage {
+        let code = source.parse::<SourceCode>().unwrap();
+        let mut coverage = Coverage::new(source, &code);
+        run_observed(&code, &mut InfiniteMemoryBand::new(), &[], &mut coverage);
+        coverage
+    }
+
+    #[test]
+    fn test_listing() {
+        let coverage = cover("++[\n  ->+<\n]\ncomment\n[\n.]");
+        assert_eq!(coverage.line_counts(), vec![Some(1), Some(2), Some(2), None, Some(1), Some(0)]);
+        assert_eq!(coverage.listing(), concat!(
+            "        1:    1:++[\n",
+            "        2:    2:  ->+<\n",
+            "        2:    3:]\n",
+            "        -:    4:comment\n",
+            "        1:    5:[\n",
+            "    #####:    6:.]\n",
+            "8 of 10 commands executed\n",
+        ));
+        assert_eq!(coverage.unexecuted(), vec![(6, 1), (6, 2)]);
+    }
+
+    #[test]
+    fn test_unexecuted_commands() {
+        let coverage = cover("+[-]\n[+\t.]+");
+        assert_eq!(coverage.unexecuted(), vec![(2, 2), (2, 4), (2, 5)]);
+        assert_eq!(coverage.listing(), concat!(
+            "        1:    1:+[-]\n",
+            "        1:    2:[+\t.]+\n",
+            "         :     : ^\t^^\n",
+            "6 of 9 commands executed\n",
+        ));
+    }
+
+    #[test]
+    fn test_lcov() {
+        let coverage = cover("+\n[-]\n[\n.\n]");
+        let mut out = Vec::new();
+        coverage.write_lcov(&mut out, "test.b").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+TN:
+SF:test.b
+DA:1,1
+DA:2,1
+DA:3,1
+DA:4,0
+DA:5,0
+LF:5
+LH:3
+end_of_record
+");
+    }
+}
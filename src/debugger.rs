@@ -0,0 +1,490 @@
+use super::machine::{Machine, Status};
+use super::observer::Instruction;
+use super::memoryband::MemoryBand;
+use super::settings::Settings;
+use super::sourcecode::{LineIndex, SourceCode};
+use super::watchpoint::{Watchpoint, Watchpoints};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+Commands:
+  break LINE[:COLUMN]  (b)  set a breakpoint at the command at or behind the position
+  delete N             (d)  remove breakpoint N
+  watch CELL           (wa) stop when CELL is written
+  watch CELL change         stop when the value of CELL changes
+  watch CELL == VALUE       stop when CELL changes to VALUE
+  watch head < N            stop when the head moves left of position N
+  watch head > N            stop when the head moves right of position N
+  unwatch N                 remove watchpoint N
+  list                 (l)  list all breakpoints and watchpoints
+  step [N]             (s)  execute the next N instructions, 1 by default
+  next                 (n)  like step, but executes a loop at once
+  continue             (c)  run until a breakpoint is hit or the program stops
+  reverse-step [N]     (rs) undo the last N instructions, 1 by default
+  reverse-continue [CELL]
+                       (rc) run backwards until a breakpoint or the last write to CELL
+  where                (w)  show the current position in the source code
+  mem [RADIUS]         (m)  show the cells around the head, 8 (at most 64) to each side
+  set OFFSET VALUE          write VALUE to the cell OFFSET cells right of the head
+  input TEXT           (i)  feed TEXT and a newline to the program's input
+  eof                       mark the end of the program's input
+  help                 (h)  show this help
+  quit                 (q)  leave the debugger
+
+CELL is a position relative to the cell the head started on, or @INDEX for the cell at INDEX
+of the memory band counted from its leftmost cell, e.g. @15000 for the starting cell of the
+finite band.";
+
+/// Largest number of cells shown to each side of the head by `mem`
+const MAX_RADIUS: isize = 64;
+
+/// Writes a table of the cells from `radius` cells left to `radius` cells right of the head,
+/// labeled with their offset from the head or, if `positions` is set, with their position.
+/// A negative `radius` counts like its absolute value and at most [`MAX_RADIUS`] cells are shown.
+pub(crate) fn write_cells<M: MemoryBand, W: Write>(band: &M, radius: isize, positions: bool, out: &mut W) -> io::Result<()> {
+    let radius = radius.checked_abs().map_or(MAX_RADIUS, |radius| radius.min(MAX_RADIUS));
+    let mut labels = String::from(if positions { "cell  " } else { "offset" });
+    let mut values = String::from("value ");
+    for offset in -radius..=radius {
+        let label = if positions { band.position() + offset } else { offset };
+        labels.push_str(&format!(" {:>5}", label));
+        let value = match band.peek(offset) {
+            Some(value) => value.to_string(),
+            None => String::from("-"),
+        };
+        if offset == 0 {
+            values.push_str(&format!(" {:>5}", format!("[{}]", value)));
+        } else {
+            values.push_str(&format!(" {:>5}", value));
+        }
+    }
+    writeln!(out, "{}\n{}", labels, values)
+}
+
+/// An interactive source-level debugger built on a [`Machine`].
+///
+/// The debugger reads commands line by line, see `help` for a list of them. Breakpoints are set
+/// by line and column of the source code and the current position is shown by underlining the
+/// next command in its source line.
+/// Watchpoints stop the execution when cells are written or the head moves too far.
+/// The execution is recorded, so it can also be run backwards.
+pub struct Debugger<M> {
+    lines: LineIndex,
+    machine: Machine<M, Watchpoints>,
+    /// Instruction indices the execution stops at
+    breakpoints: Vec<usize>,
+}
+
+impl<M: MemoryBand> Debugger<M> {
+    /// Creates a debugger for `code`, which was parsed from `source`, running on `band`
+    pub fn new(source: String, code: &SourceCode, band: M, settings: Settings) -> Debugger<M> {
+        let mut machine = Machine::with_observer(code, band, settings, Watchpoints::new());
+        machine.record_history();
+        Debugger {
+            lines: LineIndex::new(&source),
+            machine,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<M, Watchpoints> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<M, Watchpoints> {
+        &mut self.machine
+    }
+
+    /// Consumes the debugger, returning its machine
+    pub fn into_machine(self) -> Machine<M, Watchpoints> {
+        self.machine
+    }
+
+    /// Reads and executes commands from `commands` until `quit` or the end of the commands,
+    /// writing all messages and the program's output to `out`
+    pub fn run<R: BufRead, W: Write>(&mut self, commands: R, out: &mut W) -> io::Result<()> {
+        writeln!(out, "Debugging {} instructions, type 'help' for a list of commands.", self.machine.len())?;
+        self.show_position(out)?;
+        write!(out, "(bfdb) ")?;
+        out.flush()?;
+        for line in commands.lines() {
+            if !self.execute(line?.trim(), out)? {
+                return Ok(());
+            }
+            write!(out, "(bfdb) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    /// Executes a single command, returns false if the debugger should quit
+    fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let (command, rest) = match line.find(' ') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => (),
+            "break" | "b" => match self.parse_position(rest) {
+                Some(pc) => {
+                    self.breakpoints.push(pc);
+                    let (line, column) = self.line_col(pc);
+                    writeln!(out, "Breakpoint {} at {}:{}", self.breakpoints.len(), line, column)?;
+                }
+                None => writeln!(out, "Expected a position LINE[:COLUMN] with a command at or behind it.")?,
+            },
+            "delete" | "d" => match rest.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= self.breakpoints.len() => {
+                    self.breakpoints.remove(n - 1);
+                }
+                _ => writeln!(out, "There is no breakpoint '{}'.", rest)?,
+            },
+            "watch" | "wa" => match self.parse_watchpoint(rest) {
+                Some(watchpoint) => {
+                    let i = self.machine.observer_mut().add(watchpoint);
+                    writeln!(out, "Watchpoint {}: {}", i + 1, watchpoint)?;
+                }
+                None => writeln!(out, "Expected CELL, CELL change, CELL == VALUE, head < N or head > N.")?,
+            },
+            "unwatch" => match rest.parse::<usize>() {
+                Ok(n) if n >= 1 && self.machine.observer_mut().remove(n - 1).is_some() => (),
+                _ => writeln!(out, "There is no watchpoint '{}'.", rest)?,
+            },
+            "list" | "l" => {
+                for (i, pc) in self.breakpoints.iter().enumerate() {
+                    let (line, column) = self.line_col(*pc);
+                    writeln!(out, "{}: {}:{}", i + 1, line, column)?;
+                }
+                for (i, watchpoint) in self.machine.observer().watchpoints().iter().enumerate() {
+                    writeln!(out, "Watchpoint {}: {}", i + 1, watchpoint)?;
+                }
+            }
+            "step" | "s" => {
+                let n = if rest.is_empty() { Ok(1) } else { rest.parse::<u64>() };
+                match n {
+                    Ok(mut n) => {
+                        let status = self.machine.run_until(|m| {
+                            if n == 0 || m.observer().is_hit() {
+                                return true;
+                            }
+                            n -= 1;
+                            false
+                        });
+                        self.report(status, out)?;
+                    }
+                    Err(_) => writeln!(out, "Expected a number of steps.")?,
+                }
+            }
+            "next" | "n" => {
+                let status = match self.machine.loop_exit() {
+                    Some(exit) => self.resume(|m| m.pc() == exit),
+                    None => self.machine.step(),
+                };
+                self.report(status, out)?;
+            }
+            "continue" | "c" => {
+                let status = self.resume(|_| false);
+                self.report(status, out)?;
+            }
+            "reverse-step" | "rs" => match if rest.is_empty() { Ok(1) } else { rest.parse::<u64>() } {
+                Ok(n) => {
+                    let mut stepped = true;
+                    for _ in 0..n {
+                        stepped = self.machine.step_back();
+                        if !stepped {
+                            break;
+                        }
+                    }
+                    self.report_reverse(stepped, out)?;
+                }
+                Err(_) => writeln!(out, "Expected a number of steps.")?,
+            },
+            "reverse-continue" | "rc" => {
+                let cell = if rest.is_empty() { Ok(None) } else { self.parse_cell(rest).map(Some).ok_or(()) };
+                match cell {
+                    Ok(cell) => {
+                        let stepped = self.reverse(cell);
+                        self.report_reverse(stepped, out)?;
+                    }
+                    Err(_) => writeln!(out, "Expected a cell.")?,
+                }
+            }
+            "where" | "w" => self.show_position(out)?,
+            "mem" | "m" => {
+                let radius = if rest.is_empty() { Ok(8) } else { rest.parse::<isize>() };
+                match radius {
+                    Ok(radius) => write_cells(self.machine.band(), radius, false, out)?,
+                    Err(_) => writeln!(out, "Expected a radius.")?,
+                }
+            }
+            "set" => {
+                let mut args = rest.split_whitespace();
+                let offset = args.next().and_then(|s| s.parse::<isize>().ok());
+                let value = args.next().and_then(|s| s.parse::<u8>().ok());
+                match (offset, value) {
+                    (Some(offset), Some(value)) if self.machine.band().peek(offset).is_some() => {
+                        let max_memory = self.machine.settings().max_memory;
+                        let band = self.machine.band_mut();
+                        let written = match max_memory {
+                            Some(max_cells) => band.poke_within(offset, value, max_cells),
+                            None => {
+                                band.poke(offset, value);
+                                Ok(())
+                            }
+                        };
+                        if let Err(e) = written {
+                            writeln!(out, "{}", e)?;
+                        }
+                    }
+                    (Some(_), Some(_)) => writeln!(out, "There is no cell at that offset.")?,
+                    _ => writeln!(out, "Expected an offset and a value from 0 to 255.")?,
+                }
+            }
+            "input" | "i" => {
+                self.machine.feed_input(rest.as_bytes());
+                self.machine.feed_input(b"\n");
+            }
+            "eof" => self.machine.close_input(),
+            "help" | "h" => writeln!(out, "{}", HELP)?,
+            "quit" | "q" => return Ok(false),
+            _ => writeln!(out, "Unknown command '{}', type 'help' for a list of commands.", command)?,
+        }
+        Ok(true)
+    }
+
+    /// Executes at least one instruction, then runs until a breakpoint, a watchpoint or `stop` is
+    /// reached
+    fn resume<F>(&mut self, mut stop: F) -> Status
+    where F: FnMut(&Machine<M, Watchpoints>) -> bool {
+        match self.machine.step() {
+            Status::Running => (),
+            status => return status,
+        }
+        let breakpoints = &self.breakpoints;
+        self.machine.run_until(|m| m.observer().is_hit() || breakpoints.contains(&m.pc()) || stop(m))
+    }
+
+    /// Undoes at least one instruction, then goes back until a breakpoint or the instruction that
+    /// wrote `cell` is reached. Returns false if the start of the history was reached before.
+    fn reverse(&mut self, cell: Option<isize>) -> bool {
+        loop {
+            if !self.machine.step_back() {
+                return false;
+            }
+            let pc = self.machine.pc();
+            let wrote = match (cell, self.machine.instruction_at(pc)) {
+                (Some(cell), Some(Instruction::Add(_))) | (Some(cell), Some(Instruction::Read)) => {
+                    self.machine.band().position() == cell
+                }
+                _ => false,
+            };
+            if wrote || self.breakpoints.contains(&pc) {
+                return true;
+            }
+        }
+    }
+
+    /// Prints where running backwards stopped
+    fn report_reverse<W: Write>(&mut self, stepped: bool, out: &mut W) -> io::Result<()> {
+        // replaying from a checkpoint may trigger watchpoints again
+        self.machine.observer_mut().take_hit();
+        if !stepped {
+            writeln!(out, "Reached the start of the recorded execution.")?;
+        }
+        self.show_position(out)
+    }
+
+    /// Prints the program's output and why the execution stopped
+    fn report<W: Write>(&mut self, status: Status, out: &mut W) -> io::Result<()> {
+        let output = self.machine.take_output();
+        if !output.is_empty() {
+            write!(out, "[out]: ")?;
+            out.write_all(&output)?;
+            writeln!(out)?;
+        }
+        let watch_hit = self.machine.observer_mut().take_hit();
+        if let Some((i, description)) = &watch_hit {
+            writeln!(out, "Watchpoint {} hit: {}.", i + 1, description)?;
+        }
+        match status {
+            Status::Running if watch_hit.is_some() => (),
+            Status::Running => {
+                if let Some(i) = self.breakpoints.iter().position(|pc| *pc == self.machine.pc()) {
+                    writeln!(out, "Breakpoint {} hit.", i + 1)?;
+                }
+            }
+            Status::NeedsInput => writeln!(out, "The program waits for input, use 'input TEXT' or 'eof'.")?,
+            Status::Halted => {
+                return writeln!(out, "The program terminated after {} steps.", self.machine.steps());
+            }
+            Status::Error(e) => writeln!(out, "Error: {}", e)?,
+        }
+        self.show_position(out)
+    }
+
+    /// Prints the source line of the next instruction with the instruction underlined
+    fn show_position<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let span = match self.machine.span_at(self.machine.pc()) {
+            Some(span) => span,
+            None => return writeln!(out, "The program terminated."),
+        };
+        let source = self.lines.source();
+        let (line, column) = self.lines.line_col(span.start);
+        let text = source.lines().nth(line - 1).unwrap_or("");
+        let line_end = source[span.start..].find('\n').map_or(source.len(), |i| span.start + i);
+        let width = source[span.start..span.end.min(line_end)].chars().count().max(1);
+        let prefix = format!("{}:{}: ", line, column);
+        writeln!(out, "{}{}", prefix, text)?;
+        writeln!(out, "{}{}", " ".repeat(prefix.len() + column - 1), "^".repeat(width))
+    }
+
+    /// Parses `LINE[:COLUMN]` into the index of the first instruction at or behind it
+    fn parse_position(&self, s: &str) -> Option<usize> {
+        let mut parts = s.splitn(2, ':');
+        let line = parts.next()?.trim().parse().ok()?;
+        let column = match parts.next() {
+            Some(column) => column.trim().parse().ok()?,
+            None => 1,
+        };
+        self.machine.pc_at(self.lines.offset_of(line, column)?)
+    }
+
+    /// Parses the arguments of `watch`
+    fn parse_watchpoint(&self, s: &str) -> Option<Watchpoint> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+        match args.as_slice() {
+            ["head", "<", n] => Some(Watchpoint::HeadLeftOf(n.parse().ok()?)),
+            ["head", ">", n] => Some(Watchpoint::HeadRightOf(n.parse().ok()?)),
+            [cell] => Some(Watchpoint::Written(self.parse_cell(cell)?)),
+            [cell, "change"] => Some(Watchpoint::Changed(self.parse_cell(cell)?)),
+            [cell, "==", value] => Some(Watchpoint::Reaches(self.parse_cell(cell)?, value.parse().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// Parses a cell position relative to the start, or an absolute index of the band prefixed
+    /// with `@`
+    fn parse_cell(&self, s: &str) -> Option<isize> {
+        match s.strip_prefix('@') {
+            Some(index) => {
+                // the origin moves whenever the infinite band grows to the left
+                let origin = self.machine.band().origin_index() as isize;
+                index.parse::<isize>().ok().filter(|index| *index >= 0)?.checked_sub(origin)
+            }
+            None => s.parse().ok(),
+        }
+    }
+
+    fn line_col(&self, pc: usize) -> (usize, usize) {
+        self.machine
+            .span_at(pc)
+            .map_or((0, 0), |span| self.lines.line_col(span.start))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::{FiniteMemoryBand, InfiniteMemoryBand};
+
+    fn debug(source: &str, commands: &str) -> (Debugger<InfiniteMemoryBand>, String) {
+        let code = source.parse::<SourceCode>().unwrap();
+        let mut debugger = Debugger::new(source.to_owned(), &code, InfiniteMemoryBand::new(), Settings::default());
+        let mut out = Vec::new();
+        debugger.run(commands.as_bytes(), &mut out).unwrap();
+        (debugger, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_step_and_where() {
+        let (debugger, out) = debug("++\n>[-]", "step 2\nwhere\n");
+        assert_eq!(debugger.machine().pc(), 2);
+        assert!(out.contains("2:2: >[-]\n      ^\n"));
+    }
+
+    #[test]
+    fn test_breakpoint_continue() {
+        let (debugger, out) = debug("+++[>+<-]\n>.", "b 2\nc\n");
+        assert!(out.contains("Breakpoint 1 at 2:1"));
+        assert!(out.contains("Breakpoint 1 hit."));
+        assert_eq!(debugger.machine().band().peek(1), Some(3));
+    }
+
+    #[test]
+    fn test_next_steps_over_loop() {
+        let (debugger, _) = debug("+++[-]+", "s\nn\n");
+        assert_eq!(debugger.machine().pc(), 4);
+        assert_eq!(debugger.machine().band().read(), 0);
+    }
+
+    #[test]
+    fn test_mem_and_set() {
+        let (debugger, out) = debug("+", "s\nset -1 9\nmem 1\n");
+        assert_eq!(debugger.machine().band().peek(-1), Some(9));
+        assert!(out.contains("value      9   [1]     0\n"));
+    }
+
+    #[test]
+    fn test_mem_and_set_limits() {
+        let code = "+".parse::<SourceCode>().unwrap();
+        let settings = Settings { max_memory: Some(10), ..Settings::default() };
+        let mut debugger = Debugger::new(String::from("+"), &code, InfiniteMemoryBand::new(), settings);
+        let mut out = Vec::new();
+        debugger.run("mem -9223372036854775808\nmem 1000000000\nset 1000000000 1\n".as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("   64").count(), 2);
+        assert!(!out.contains("   65"));
+        assert!(out.contains("memory limit"), "{}", out);
+        assert_eq!(debugger.machine().band().snapshot().cells().len(), 1);
+    }
+
+    #[test]
+    fn test_set_far_away() {
+        let (debugger, out) = debug("<.>", "c\nset 9223372036854775807 1\n");
+        assert!(out.contains("There is no cell at that offset.\n"), "{}", out);
+        assert_eq!(debugger.machine().band().snapshot().cells(), &[0, 0]);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let (debugger, out) = debug("+>+>+[-]<<[-]<", "watch 2 == 0\nc\nwatch head < 0\nc\n");
+        assert!(out.contains("Watchpoint 1: cell 2 reaching 0\n"));
+        assert!(out.contains("Watchpoint 1 hit: cell 2 changed from 1 to 0.\n1:8: +>+>+[-]<<[-]<\n"));
+        assert!(out.contains("Watchpoint 2 hit: head moved from 0 to -1.\n"));
+        assert!(out.contains("terminated after 13 steps"));
+        assert_eq!(debugger.machine().band().peek(0), Some(0));
+    }
+
+    #[test]
+    fn test_absolute_cell_after_growing_left() {
+        let (_, out) = debug("<<+", "s\nwa @0 change\nc\n");
+        assert!(out.contains("Watchpoint 1: change of cell -2\n"), "{}", out);
+        assert!(out.contains("Watchpoint 1 hit: cell -2 changed from 0 to 1."), "{}", out);
+    }
+
+    #[test]
+    fn test_watch_absolute_cell() {
+        let code = "+>+".parse::<SourceCode>().unwrap();
+        let mut debugger = Debugger::new(String::from("+>+"), &code, FiniteMemoryBand::new(), Settings::default());
+        let mut out = Vec::new();
+        debugger.run("wa @15001 change\nc\n".as_bytes(), &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("Watchpoint 1 hit: cell 1 changed from 0 to 1."));
+    }
+
+    #[test]
+    fn test_reverse() {
+        let (debugger, out) = debug("+>++<[->+<]", "c\nrc 0\nrs 2\nrs 100\n");
+        assert!(out.contains("1:7: +>++<[->+<]\n           ^\n(bfdb) 1:5: +>++<[->+<]\n"));
+        assert!(out.contains("Reached the start of the recorded execution.\n1:1: "));
+        assert_eq!(debugger.machine().steps(), 0);
+        assert_eq!(debugger.machine().band().read(), 0);
+    }
+
+    #[test]
+    fn test_input() {
+        let (_, out) = debug(",.", "c\ninput a\nc\n");
+        assert!(out.contains("waits for input"));
+        assert!(out.contains("[out]: a\n"));
+        assert!(out.contains("terminated after 2 steps"));
+    }
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errors that abort the execution of a brainfuck program.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// Reading from the [`super::input::Input`] or writing to the [`super::output::Output`] failed
+    Io(io::Error),
+    /// The program requested input after the end of the input was reached
+    UnexpectedEof,
+    /// The execution budget given in the [`super::settings::Settings`] was used up.
+    /// Holds the number of instructions executed and bytes written up to that point.
+    BudgetExceeded { steps: u64, output_bytes: u64 },
+    /// The run was cancelled through its [`super::settings::CancellationToken`]
+    Cancelled,
+    /// The run took longer than the timeout given in the [`super::settings::Settings`]
+    TimedOut,
+    /// The memoryband would have to grow beyond the given number of cells
+    MemoryLimit { max_cells: usize },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::Io(e) => write!(f, "I/O error: {}", e),
+            RuntimeError::UnexpectedEof => write!(f, "The program tried to read past the end of the input."),
+            RuntimeError::BudgetExceeded { steps, output_bytes } => write!(
+                f,
+                "The execution budget was exceeded after {} steps and {} output bytes.",
+                steps, output_bytes
+            ),
+            RuntimeError::Cancelled => write!(f, "The program was cancelled."),
+            RuntimeError::TimedOut => write!(f, "The program exceeded its time limit."),
+            RuntimeError::MemoryLimit { max_cells } => write!(
+                f,
+                "The program exceeded the memory limit of {} cells.",
+                max_cells
+            ),
+        }
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RuntimeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(e: io::Error) -> RuntimeError {
+        RuntimeError::Io(e)
+    }
+}
@@ -0,0 +1,149 @@
+use super::error::RuntimeError;
+use super::settings::Settings;
+use super::memoryband::MemoryBand;
+use std::time::Instant;
+
+/// Number of loop back-edges between two looks at the clock
+const CLOCK_INTERVAL: u32 = 1024;
+
+/// Number of cells to each side of the head shown by the `#` debug command
+const DUMP_RADIUS: isize = 8;
+
+/// Bookkeeping of a single run that is shared by all backends, so that [`Settings`] are enforced
+/// identically everywhere.
+///
+/// Without `LIMITED`, steps and output bytes are not counted and the step budget, output budget,
+/// memory limit, timeout and cancellation are not checked, so a run without them pays nothing for
+/// them. Use it only if [`Settings::limits_execution()`] is false.
+pub(crate) struct Execution<const LIMITED: bool = true> {
+    pub settings: Settings,
+    /// Number of executed instructions, every evaluation of a loop condition counts as one
+    pub steps: u64,
+    /// Number of bytes written to the output
+    pub output_bytes: u64,
+    deadline: Option<Instant>,
+    back_edges: u32,
+}
+
+impl<const LIMITED: bool> Execution<LIMITED> {
+    pub fn new(settings: Settings) -> Execution<LIMITED> {
+        Execution {
+            deadline: settings.timeout.map(|timeout| Instant::now() + timeout),
+            settings,
+            steps: 0,
+            output_bytes: 0,
+            back_edges: 0,
+        }
+    }
+
+    /// Checks for cancellation and the deadline, called whenever a loop jumps back.
+    /// The clock is only read every [`CLOCK_INTERVAL`] calls to keep this cheap.
+    #[inline]
+    pub fn back_edge(&mut self) -> Result<(), RuntimeError> {
+        if !LIMITED {
+            return Ok(());
+        }
+        if let Some(token) = &self.settings.cancellation {
+            if token.is_cancelled() {
+                return Err(RuntimeError::Cancelled);
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            self.back_edges += 1;
+            if self.back_edges >= CLOCK_INTERVAL {
+                self.back_edges = 0;
+                if Instant::now() >= deadline {
+                    return Err(RuntimeError::TimedOut);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Accounts for one executed instruction, failing if the step budget is used up
+    #[inline]
+    pub fn step(&mut self) -> Result<(), RuntimeError> {
+        self.check_step()?;
+        self.count_step();
+        Ok(())
+    }
+
+    /// Fails if the step budget does not allow executing another instruction. An instruction
+    /// that may fail is only counted by [`Execution::count_step()`] once it succeeded.
+    #[inline]
+    pub fn check_step(&self) -> Result<(), RuntimeError> {
+        if !LIMITED {
+            return Ok(());
+        }
+        match self.settings.max_steps {
+            Some(max) if self.steps >= max => Err(self.budget_exceeded()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts one successfully executed instruction
+    #[inline]
+    pub fn count_step(&mut self) {
+        if LIMITED {
+            self.steps += 1;
+        }
+    }
+
+    /// Accounts for one written byte, failing if the output budget is used up
+    #[inline]
+    pub fn output(&mut self) -> Result<(), RuntimeError> {
+        if !LIMITED {
+            return Ok(());
+        }
+        if let Some(max) = self.settings.max_output {
+            if self.output_bytes >= max {
+                return Err(self.budget_exceeded());
+            }
+        }
+        self.output_bytes += 1;
+        Ok(())
+    }
+
+    /// Moves the reading head of `band`, respecting the memory limit
+    #[inline]
+    pub fn move_head<M: MemoryBand>(&self, band: &mut M, moves: isize) -> Result<(), RuntimeError> {
+        if !LIMITED {
+            band.move_head(moves);
+            return Ok(());
+        }
+        match self.settings.max_memory {
+            Some(max_cells) => band.move_head_within(moves, max_cells),
+            None => {
+                band.move_head(moves);
+                Ok(())
+            }
+        }
+    }
+
+    /// Executes the `#` debug command, printing the head position and the cells around it to
+    /// stderr
+    pub fn dump<M: MemoryBand>(&self, band: &M) {
+        let cells: Vec<String> = (-DUMP_RADIUS..=DUMP_RADIUS)
+            .map(|offset| match (band.peek(offset), offset) {
+                (Some(value), 0) => format!("[{}]", value),
+                (Some(value), _) => value.to_string(),
+                (None, _) => String::from("-"),
+            })
+            .collect();
+        let position = band.position();
+        eprintln!(
+            "# head at {}, cells {}..={}: {}",
+            position,
+            position - DUMP_RADIUS,
+            position + DUMP_RADIUS,
+            cells.join(" ")
+        );
+    }
+
+    fn budget_exceeded(&self) -> RuntimeError {
+        RuntimeError::BudgetExceeded {
+            steps: self.steps,
+            output_bytes: self.output_bytes,
+        }
+    }
+}
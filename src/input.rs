@@ -1,62 +1,223 @@
 use std::io::*;
 use std::collections::VecDeque;
 
-/// Fnacy type-alias for trait Iterator<Item=char>
+/// Source of the bytes read by the brainfuck `,` command.
+///
+/// Every Iterator over [`u8`] is an Input, wrap an Iterator over [`char`] in [`Utf8Input`] to
+/// feed text to a program and any [`Read`] in [`ReadInput`] to read from files, pipes or sockets.
 pub trait Input {
-    fn read_char(&mut self) -> Option<char>;
+    /// Returns the next byte, or `None` once the input is exhausted.
+    fn read_byte(&mut self) -> Result<Option<u8>>;
 }
 
-impl<I: Iterator<Item=char>> Input for I {
-    fn read_char(&mut self) -> Option<char> {
-        self.next()
+impl<I: Iterator<Item=u8>> Input for I {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        Ok(self.next())
     }
 }
 
+/// Adapter that reads the input from any [`Read`], buffering it internally.
+pub struct ReadInput<R> {
+    reader: BufReader<R>,
+}
+
+impl<R: Read> ReadInput<R> {
+    pub fn new(reader: R) -> ReadInput<R> {
+        ReadInput {
+            reader: BufReader::new(reader),
+        }
+    }
+
+    /// Unwraps the underlying reader, discarding any buffered input
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+impl<R: Read> Input for ReadInput<R> {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok([]) => return Ok(None),
+                Ok(buf) => {
+                    let byte = buf[0];
+                    self.reader.consume(1);
+                    return Ok(Some(byte));
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Adapter that feeds an Iterator over [`char`] to a program as UTF-8 encoded text.
+///
+/// Each char is handed out as its UTF-8 bytes, so non-ASCII characters take several `,`
+/// commands to read instead of being truncated to a single byte.
+pub struct Utf8Input<I> {
+    chars: I,
+    pending: VecDeque<u8>,
+}
+
+impl<I: Iterator<Item=char>> Utf8Input<I> {
+    pub fn new(chars: I) -> Utf8Input<I> {
+        Utf8Input {
+            chars,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<I: Iterator<Item=char>> Iterator for Utf8Input<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.pending.is_empty() {
+            let c = self.chars.next()?;
+            let mut buf = [0; 4];
+            self.pending.extend(c.encode_utf8(&mut buf).bytes());
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Reads the input from stdin.
+///
+/// When stdin is a terminal, the user is asked for a line of input whenever the buffer runs
+/// empty. Otherwise stdin is read silently in chunks of raw bytes, so piped input does not
+/// clutter the output with prompts.
 pub struct InputBuffer {
-    buffer: VecDeque<char>,
-    stdin: Stdin,
+    buffer: VecDeque<u8>,
+    source: Source,
+    prompt: bool,
+}
+
+/// Where an [`InputBuffer`] refills from. Stdin is only locked while it is read, so that the
+/// line editor of the interactive environment can read from it in between.
+enum Source {
+    Stdin(Stdin),
+    Reader(Box<dyn BufRead>),
 }
 
 impl InputBuffer {
+    /// Creates an InputBuffer that prompts for input only if stdin is a terminal
     pub fn new() -> InputBuffer {
-        let buffer = VecDeque::new();
+        let prompt = stdin().is_terminal();
+        InputBuffer::with_prompt(prompt)
+    }
+
+    /// Creates an InputBuffer that prompts for every line of input if `prompt` is set
+    pub fn with_prompt(prompt: bool) -> InputBuffer {
         InputBuffer {
-            buffer,
-            stdin: stdin(),
+            buffer: VecDeque::new(),
+            source: Source::Stdin(stdin()),
+            prompt,
         }
     }
 
+    /// Creates an InputBuffer that reads from `reader` instead of stdin
+    pub fn from_reader<R: BufRead + 'static>(reader: R, prompt: bool) -> InputBuffer {
+        InputBuffer {
+            buffer: VecDeque::new(),
+            source: Source::Reader(Box::new(reader)),
+            prompt,
+        }
+    }
+
+    /// Refills the buffer, leaving it empty at the end of the input
+    fn fill(&mut self) -> Result<()> {
+        match &mut self.source {
+            Source::Stdin(stdin) => self.buffer = read_chunk(&mut stdin.lock(), self.prompt)?,
+            Source::Reader(reader) => self.buffer = read_chunk(reader, self.prompt)?,
+        }
+        Ok(())
+    }
 }
 
-impl Iterator for InputBuffer {
-    type Item = char;
+/// Reads a line from `reader` if `prompt` is set, asking the user for it, and otherwise whatever
+/// is available
+fn read_chunk<R: BufRead + ?Sized>(reader: &mut R, prompt: bool) -> Result<VecDeque<u8>> {
+    let mut buf = Vec::new();
+    if prompt {
+        println!("\nType in your input:");
+        reader.read_until(b'\n', &mut buf)?;
+    } else {
+        buf.resize(4096, 0);
+        let len = reader.read(&mut buf)?;
+        buf.truncate(len);
+    }
+    Ok(buf.into_iter().collect())
+}
 
-    fn next(&mut self) -> Option<char> {
-        match self.buffer.pop_front() {
-            Some(c) => Some(c),
-            None => {
-                let mut buf_str = String::new();
-                println!("\nType in your input:");
-                if let Err(_) =  self.stdin.read_line(&mut buf_str) {
-                    return None;
-                }
-                self.buffer = buf_str.chars().collect();
-                self.buffer.pop_front()
+impl Default for InputBuffer {
+    fn default() -> InputBuffer {
+        InputBuffer::new()
+    }
+}
+
+impl Input for InputBuffer {
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        while self.buffer.is_empty() {
+            match self.fill() {
+                Ok(()) if self.buffer.is_empty() => return Ok(None),
+                Ok(()) => (),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
             }
         }
+        Ok(self.buffer.pop_front())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+
+    /// A reader that always fails
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> Result<usize> {
+            Err(Error::other("broken"))
+        }
+    }
+
     #[test]
     fn test_next() {
-        let mut buf = InputBuffer {
-            buffer: vec!['a', 'b'].into_iter().collect(),
-            stdin: stdin(),
-        };
-        assert_eq!(buf.next(), Some('a'));
-        assert_eq!(buf.next(), Some('b'));
+        let mut buf = InputBuffer::from_reader(Cursor::new(vec![b'c']), false);
+        buf.buffer = vec![b'a', b'b'].into_iter().collect();
+        assert_eq!(buf.read_byte().unwrap(), Some(b'a'));
+        assert_eq!(buf.read_byte().unwrap(), Some(b'b'));
+        assert_eq!(buf.read_byte().unwrap(), Some(b'c'));
+        assert_eq!(buf.read_byte().unwrap(), None);
+    }
+
+    #[test]
+    fn test_input_buffer_error() {
+        let mut buf = InputBuffer::from_reader(BufReader::new(Broken), false);
+        assert_eq!(buf.read_byte().unwrap_err().to_string(), "broken");
+    }
+
+    #[test]
+    fn test_utf8_input() {
+        let mut input = Utf8Input::new("aé".chars());
+        assert_eq!(input.read_byte().unwrap(), Some(b'a'));
+        assert_eq!(input.read_byte().unwrap(), Some(0xC3));
+        assert_eq!(input.read_byte().unwrap(), Some(0xA9));
+        assert_eq!(input.read_byte().unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_input() {
+        let mut input = ReadInput::new(Cursor::new(vec![0, 200]));
+        assert_eq!(input.read_byte().unwrap(), Some(0));
+        assert_eq!(input.read_byte().unwrap(), Some(200));
+        assert_eq!(input.read_byte().unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_input_error() {
+        assert!(ReadInput::new(Broken).read_byte().is_err());
     }
 }
@@ -3,66 +3,304 @@
 //! # Usage
 //!
 //! The central piece if this interpreter is the `SourceCode` struct and its `run()` method.
+//! To execute a program step by step, e.g. in a debugger or GUI, create a `machine::Machine` from it.
+//! Tools like tracers and profilers implement `observer::Observer` and pass it to `run_observed()`.
 
 use crate::memoryband::*;
 use crate::sourcecode::*;
 use crate::input::*;
 use crate::output::*;
 use crate::args::*;
+use crate::snapshot::*;
+use crate::debugger::*;
+use crate::trace::*;
+use crate::profile::*;
+use crate::stats::*;
+use crate::coverage::*;
+use crate::repl::*;
 use std::error::Error;
-use std::fs;
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
 
 pub mod memoryband;
 pub mod sourcecode;
 pub mod input;
 pub mod output;
 pub mod args;
+pub mod snapshot;
+pub mod error;
+pub mod settings;
+pub mod machine;
+pub mod debugger;
+pub mod observer;
+pub mod trace;
+pub mod profile;
+pub mod stats;
+pub mod coverage;
+pub mod watchpoint;
+pub mod repl;
+mod execution;
 
-fn run_file<M>(args: Args) -> Result<(), Box<dyn Error>> 
+/// Creates the memoryband to run on, restored from `--load-memory` if it was given
+fn load_band<M>(args: &Args) -> Result<M, Box<dyn Error>>
+    where M: MemoryBand {
+    match &args.load_memory {
+        Some(path) => Ok(M::restore(&MemorySnapshot::load(path)?)?),
+        None => Ok(M::new()),
+    }
+}
+
+/// Writes `band` to the snapshot file given by `--save-memory`, if any
+fn save_band<M>(band: &M, args: &Args) -> Result<(), Box<dyn Error>>
     where M: MemoryBand {
-    let code = fs::read_to_string(args.input_path.into_os_string())?.parse::<SourceCode>()?;
-    let mut stdin = InputBuffer::new();
-    let mut stdout = StdOutput();
-    code.run::<_, StdOutput, M>(&mut stdin, &mut stdout);
+    if let Some(path) = &args.save_memory {
+        band.snapshot().save(path)?;
+    }
+    Ok(())
+}
+
+/// Creates the input for the program's `,` commands from `--input`, `--input-file` or the
+/// input `embedded` in the source file, falling back to `stdin`
+fn program_input<I>(args: &Args, embedded: Option<&str>, stdin: I) -> Result<Box<dyn Input>, Box<dyn Error>>
+    where I: Input + 'static {
+    if let Some(input) = &args.input {
+        Ok(Box::new(input.clone().into_bytes().into_iter()))
+    } else if let Some(path) = &args.input_file {
+        Ok(Box::new(ReadInput::new(File::open(path)?)))
+    } else if let Some(input) = embedded {
+        Ok(Box::new(input.to_owned().into_bytes().into_iter()))
+    } else {
+        Ok(Box::new(stdin))
+    }
+}
+
+/// Reads the source code given by `--eval` or `input_path`, where a path of `-` stands for stdin
+fn read_source(args: &Args) -> Result<String, Box<dyn Error>> {
+    match (&args.eval, &args.input_path) {
+        (Some(code), _) => Ok(code.clone()),
+        (None, Some(path)) if path.as_os_str() == "-" => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok(source)
+        }
+        (None, Some(path)) => Ok(fs::read_to_string(path)?),
+        (None, None) => Err("No brainfuck code given. Pass the path to a source file, `-` to read the code from stdin, \
+             `-e <CODE>` or `--interactive`, see `--help` for details.".into()),
+    }
+}
+
+/// Writes the annotated listing and the LCOV tracefile to the files given by `--coverage` and
+/// `--lcov`
+fn write_coverage(coverage: &Coverage, args: &Args) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &args.coverage {
+        fs::write(path, coverage.listing())?;
+    }
+    if let Some(path) = &args.lcov {
+        let source_name = match (&args.eval, &args.input_path) {
+            (None, Some(path)) => path.to_string_lossy(),
+            _ => "-".into(),
+        };
+        let mut out = BufWriter::new(File::create(path)?);
+        coverage.write_lcov(&mut out, &source_name)?;
+        out.flush()?;
+    }
     Ok(())
 }
 
-fn run_interpreter<M>() 
+fn run_file<M>(args: Args) -> Result<(), Box<dyn Error>> 
     where M: MemoryBand {
-    println!("Welcome to the rsbrainfuck interpreter. Type 'exit' to exit the interpreter");
-    let mut band = M::new();
-    let mut stdin = InputBuffer::new();
-    let mut stdout = StdOutput();
-    loop {
-        let mut string = String::new();
-        if let Err(e) = io::stdin().read_line(&mut string) {
-            eprintln!("{}", e);
-            continue;
+    let source = read_source(&args)?;
+    let (source, embedded) = if args.embedded_input {
+        split_embedded_input(&source)
+    } else {
+        (source.as_str(), None)
+    };
+    let code = SourceCode::parse_with(source, &args.parse_options())?;
+    let mut band = load_band::<M>(&args)?;
+    let stdin = if args.no_prompt {
+        InputBuffer::with_prompt(false)
+    } else {
+        InputBuffer::new()
+    };
+    let mut stdin = program_input(&args, embedded, stdin)?;
+    let mut stdout = StdOutput::new();
+    let mut tracer = match &args.trace {
+        Some(path) => {
+            let mut tracer = Tracer::new(BufWriter::new(File::create(path)?), source)
+                .sample_every(args.trace_every);
+            if let Some(kinds) = &args.trace_only {
+                tracer = tracer.only_kinds(kinds.split(',').map(|kind| kind.trim().to_owned()).collect());
+            }
+            Some(tracer)
+        }
+        None => None,
+    };
+    let mut profiler = if args.profile { Some(Profiler::new(source, &code)) } else { None };
+    let mut stats = if args.stats { Some(Stats::new(source)) } else { None };
+    let mut coverage = if args.coverage.is_some() || args.lcov.is_some() {
+        Some(Coverage::new(source, &code))
+    } else {
+        None
+    };
+    if tracer.is_none() && profiler.is_none() && stats.is_none() && coverage.is_none() {
+        code.run_with(&mut band, &mut *stdin, &mut stdout, &args.settings())?;
+    } else {
+        let mut observers = (&mut tracer, (&mut profiler, (&mut stats, &mut coverage)));
+        let result = code.run_observed(&mut band, &mut *stdin, &mut stdout, &args.settings(), &mut observers);
+        if let Some(tracer) = tracer {
+            tracer.finish()?;
+        }
+        if let Some(profiler) = profiler {
+            eprint!("{}", profiler.report(10));
         }
-        if string.starts_with("exit") {
-            println!("Exiting...");
-            break;
+        if let Some(stats) = stats {
+            eprint!("{}", stats.report());
         }
-        
-        match string.parse::<SourceCode>() {
-            Ok(code) => {
-                print!("[out]: ");
-                code.run_on_band::<_,_,M>(&mut band, &mut stdin, &mut stdout);
-            },
-            Err(e) => eprintln!("{}", e),
+        if let Some(coverage) = coverage {
+            write_coverage(&coverage, &args)?;
+        }
+        result?;
+    }
+    save_band(&band, &args)
+}
+
+fn run_debugger<M>(args: Args) -> Result<(), Box<dyn Error>>
+    where M: MemoryBand {
+    if args.eval.is_none() && args.input_path.as_ref().is_some_and(|path| path.as_os_str() == "-") {
+        return Err("The debugger reads its commands from stdin, so the code cannot be read from stdin too.".into());
+    }
+    let source = read_source(&args)?;
+    let (code, embedded) = if args.embedded_input {
+        split_embedded_input(&source)
+    } else {
+        (source.as_str(), None)
+    };
+    let code = SourceCode::parse_with(code, &args.parse_options())?;
+    let band = load_band::<M>(&args)?;
+    let mut debugger = Debugger::new(source.clone(), &code, band, args.settings());
+
+    let input = match (&args.input, &args.input_file, embedded) {
+        (Some(input), _, _) => Some(input.clone().into_bytes()),
+        (None, Some(path), _) => Some(fs::read(path)?),
+        (None, None, Some(input)) => Some(input.as_bytes().to_vec()),
+        (None, None, None) => None,
+    };
+    if let Some(input) = input {
+        debugger.machine_mut().feed_input(&input);
+        debugger.machine_mut().close_input();
+    }
+
+    debugger.run(io::stdin().lock(), &mut io::stdout())?;
+    save_band(debugger.machine().band(), &args)
+}
+
+/// Path of the file the interactive interpreter keeps the entered lines in, inside the user's
+/// data directory
+fn history_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("rsbrainfuck").join("history"))
+}
+
+/// Creates the input for the `,` commands of the code entered in the interactive interpreter
+fn interpreter_input(args: &Args) -> Result<Box<dyn Input>, Box<dyn Error>> {
+    // the program input shares stdin with the entered code, so it has to be read line by line
+    program_input(args, None, InputBuffer::with_prompt(true))
+}
+
+/// Path of the file the interactive interpreter appends the code and band-changing commands of
+/// every session to, so that `:replay` can continue an earlier session
+fn session_log() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("rsbrainfuck").join("sessions"))
+}
+
+/// Appends the history of `repl` to the session log at `path`, behind a command that recreates
+/// the band the session started on
+fn append_session<M>(path: &Path, repl: &Repl<M>, args: &Args) -> io::Result<()>
+    where M: MemoryBand {
+    if repl.history().is_empty() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut log = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    match &args.load_memory {
+        // the log is replayed from wherever the next session is started
+        Some(snapshot) => {
+            let snapshot = fs::canonicalize(snapshot).unwrap_or_else(|_| snapshot.clone());
+            writeln!(log, ":load {}", snapshot.display())?;
+        }
+        None => writeln!(log, ":reset")?,
+    }
+    for entry in repl.history() {
+        writeln!(log, "{}", entry)?;
+    }
+    Ok(())
+}
+
+fn run_interpreter<M>(args: Args) -> Result<(), Box<dyn Error>>
+    where M: MemoryBand {
+    println!("Welcome to the rsbrainfuck interpreter. Type 'exit' to exit the interpreter or ':help' for a list of commands");
+    let band = load_band::<M>(&args)?;
+    let mut stdin = interpreter_input(&args)?;
+    let mut stdout = StdOutput::new();
+    let mut repl = Repl::new(band, args.settings(), args.parse_options());
+    let mut editor = DefaultEditor::new()?;
+    let history = history_file();
+    if let Some(path) = &history {
+        // there is no history yet on the first start
+        let _ = editor.load_history(path);
+    }
+    loop {
+        let line = match editor.readline(repl.prompt()) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    editor.add_history_entry(line.as_str())?;
+                }
+                line
+            }
+            // ctrl-c discards the unfinished code
+            Err(ReadlineError::Interrupted) => {
+                repl.cancel();
+                continue;
+            }
+            Err(ReadlineError::Eof) => String::from("exit"),
+            Err(e) => return Err(e.into()),
         };
+        if !repl.execute(&line, &mut *stdin, &mut stdout, &mut io::stdout())? {
+            if let Some(path) = &history {
+                let saved = path.parent().map_or(Ok(()), fs::create_dir_all)
+                    .map_err(ReadlineError::from)
+                    .and_then(|_| editor.save_history(path));
+                if let Err(e) = saved {
+                    eprintln!("Could not save the history to '{}': {}", path.display(), e);
+                }
+            }
+            if let Some(path) = session_log() {
+                if let Err(e) = append_session(&path, &repl, &args) {
+                    eprintln!("Could not save the session to '{}': {}", path.display(), e);
+                }
+            }
+            return save_band(repl.band(), &args);
+        }
     }
 }
 
 pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
-    if args.interactive {
+    if args.debug {
         if args.infinite_memory {
-            run_interpreter::<InfiniteMemoryBand>();
+            run_debugger::<InfiniteMemoryBand>(args)
         } else {
-            run_interpreter::<FiniteMemoryBand>();
+            run_debugger::<FiniteMemoryBand>(args)
+        }
+    } else if args.interactive {
+        if args.infinite_memory {
+            run_interpreter::<InfiniteMemoryBand>(args)
+        } else {
+            run_interpreter::<FiniteMemoryBand>(args)
         }
-        Ok(())
     } else {
         if args.infinite_memory {
             run_file::<InfiniteMemoryBand>(args)
@@ -72,3 +310,93 @@ pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn read_all(input: &mut dyn Input) -> Vec<u8> {
+        std::iter::from_fn(|| input.read_byte().unwrap()).collect()
+    }
+
+    fn input_of(args: &[&str], embedded: Option<&str>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let args = Args::from_iter_safe(args).unwrap();
+        let mut input = program_input(&args, embedded, b"stdin".iter().copied())?;
+        Ok(read_all(&mut *input))
+    }
+
+    #[test]
+    fn test_program_input_option() {
+        let input = input_of(&["rsbrainfuck", "--input", "option", "x.b"], Some("embedded")).unwrap();
+        assert_eq!(input, b"option");
+
+        // --input wins even if a file is given too, which the command line does not allow
+        let mut args = Args::from_iter_safe(&["rsbrainfuck", "--input", "option", "x.b"]).unwrap();
+        args.input_file = Some(PathBuf::from("missing-file"));
+        let mut input = program_input(&args, None, std::iter::empty()).unwrap();
+        assert_eq!(read_all(&mut *input), b"option");
+    }
+
+    #[test]
+    fn test_program_input_file() {
+        let path = std::env::temp_dir().join(format!("rsbrainfuck-{}-input", std::process::id()));
+        fs::write(&path, "file").unwrap();
+        let input = input_of(&["rsbrainfuck", "--input-file", path.to_str().unwrap(), "x.b"], Some("embedded"));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(input.unwrap(), b"file");
+
+        assert!(input_of(&["rsbrainfuck", "--input-file", "missing-file", "x.b"], None).is_err());
+    }
+
+    #[test]
+    fn test_interpreter_input_leaves_stdin_unlocked() {
+        let args = Args::from_iter_safe(&["rsbrainfuck", "-i"]).unwrap();
+        let _input = interpreter_input(&args).unwrap();
+
+        // the line editor locks stdin to read the next line of code while the input exists
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut stdin = io::stdin().lock();
+            sender.send(()).unwrap();
+            let _ = io::BufRead::read_line(&mut stdin, &mut String::new());
+        });
+        assert!(receiver.recv_timeout(std::time::Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_session_log() {
+        let path = std::env::temp_dir().join(format!("rsbrainfuck-{}-sessions", std::process::id()));
+        let args = Args::from_iter_safe(&["rsbrainfuck", "-i"]).unwrap();
+        for lines in [&["+", "+", ":mem"][..], &["+", "[", "->+<", "]", "+", ":set 5 9"]] {
+            let mut repl = Repl::new(InfiniteMemoryBand::new(), args.settings(), args.parse_options());
+            for line in lines {
+                repl.execute(line, &mut std::iter::empty(), &mut Vec::<u8>::new(), &mut io::sink()).unwrap();
+            }
+            append_session(&path, &repl, &args).unwrap();
+        }
+
+        let mut repl = Repl::new(InfiniteMemoryBand::new(), args.settings(), args.parse_options());
+        let replay = format!(":replay {}", path.display());
+        repl.execute(&replay, &mut std::iter::empty(), &mut Vec::<u8>::new(), &mut io::sink()).unwrap();
+        let log = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(log, ":reset\n+\n+\n:reset\n+\n[\n->+<\n]\n+\n:set 5 9\n");
+        assert_eq!((repl.band().peek(0), repl.band().peek(1), repl.band().peek(5)), (Some(1), Some(1), Some(9)));
+    }
+
+    #[test]
+    fn test_no_source() {
+        let args = Args::from_iter_safe(&["rsbrainfuck"]).unwrap();
+        let message = read_source(&args).unwrap_err().to_string();
+        assert!(message.starts_with("No brainfuck code given."));
+        assert!(message.contains("`-e <CODE>`"));
+    }
+
+    #[test]
+    fn test_program_input_embedded_and_stdin() {
+        assert_eq!(input_of(&["rsbrainfuck", "x.b"], Some("embedded")).unwrap(), b"embedded");
+        assert_eq!(input_of(&["rsbrainfuck", "x.b"], Some("")).unwrap(), b"");
+        assert_eq!(input_of(&["rsbrainfuck", "x.b"], None).unwrap(), b"stdin");
+    }
+}
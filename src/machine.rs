@@ -0,0 +1,746 @@
+use super::memoryband::MemoryBand;
+use super::sourcecode::{BfCommand, SourceCode, Span};
+use super::settings::Settings;
+use super::execution::Execution;
+use super::error::RuntimeError;
+use super::observer::{Instruction, Observer};
+use super::snapshot::MemorySnapshot;
+use std::collections::VecDeque;
+
+/// Maximum number of instructions kept in the undo log
+const HISTORY_LIMIT: usize = 1 << 20;
+/// Instructions between two checkpoints of the history at first
+const CHECKPOINT_INTERVAL: u64 = 1 << 16;
+/// Maximum number of checkpoints, when there are more every second one is dropped
+const MAX_CHECKPOINTS: usize = 64;
+
+/// Instruction of the flat program run by a [`Machine`].
+/// The jump targets of the loops are resolved when the program is compiled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Move(isize),
+    Add(u8),
+    Print,
+    Read,
+    /// Start of a loop, jumps behind the `LoopEnd` at the given index if the current cell is 0
+    LoopStart(usize),
+    /// End of a loop, jumps behind the `LoopStart` at the given index if the current cell is not 0
+    LoopEnd(usize),
+    Debug,
+}
+
+/// Flattens the nested loops of `code` into `ops`, keeping the span of every op in `spans`.
+/// The ops of a loop's brackets get the spans of the brackets alone.
+fn compile(code: &SourceCode, ops: &mut Vec<Op>, spans: &mut Vec<Span>) {
+    for (c, span) in code.commands.iter().zip(code.spans.iter()) {
+        match c {
+            BfCommand::Move(i) => ops.push(Op::Move(*i)),
+            BfCommand::Add(i) => ops.push(Op::Add(*i)),
+            BfCommand::Print => ops.push(Op::Print),
+            BfCommand::Read => ops.push(Op::Read),
+            BfCommand::Debug => ops.push(Op::Debug),
+            BfCommand::Loop(body) => {
+                let start = ops.len();
+                ops.push(Op::LoopStart(0));
+                spans.push(Span { start: span.start, end: span.start + 1 });
+                compile(body, ops, spans);
+                let end = ops.len();
+                ops.push(Op::LoopEnd(start));
+                spans.push(Span { start: span.end - 1, end: span.end });
+                ops[start] = Op::LoopStart(end);
+                continue;
+            }
+        }
+        spans.push(*span);
+    }
+}
+
+/// Information to undo an executed instruction
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    /// Index of the instruction
+    pc: usize,
+    /// Value of the current cell before the instruction
+    before: u8,
+    /// Whether the instruction consumed a byte of the input
+    consumed_input: bool,
+}
+
+/// State of a machine to replay the program from, for going back further than the undo log
+/// reaches
+#[derive(Debug)]
+struct Checkpoint {
+    steps: u64,
+    pc: usize,
+    band: MemorySnapshot,
+    /// Number of input bytes consumed before
+    consumed_input: usize,
+    output_bytes: u64,
+}
+
+/// Recorded execution of a [`Machine`]. The undo log reverts the latest instructions one by one,
+/// older states are recovered by replaying the program from a checkpoint.
+#[derive(Debug)]
+struct History {
+    log: VecDeque<Undo>,
+    log_limit: usize,
+    checkpoints: Vec<Checkpoint>,
+    interval: u64,
+    /// All input bytes consumed since the recording started, to replay the program
+    consumed_input: Vec<u8>,
+}
+
+/// State of a [`Machine`] after executing instructions.
+#[derive(Debug)]
+pub enum Status {
+    /// There are instructions left to execute
+    Running,
+    /// The next instruction is a `,`, but no input is available. Provide some with
+    /// [`Machine::feed_input()`] or mark the end of the input with [`Machine::close_input()`].
+    NeedsInput,
+    /// The program terminated
+    Halted,
+    /// The program was stopped by an error, the failed instruction has not been executed
+    Error(RuntimeError),
+}
+
+/// A resumable interpreter that executes a brainfuck program instruction by instruction.
+///
+/// Unlike [`SourceCode::run()`], a Machine never blocks: when `,` finds no input, it pauses with
+/// [`Status::NeedsInput`] until input is fed to it. The output is collected internally and can
+/// be fetched with [`Machine::take_output()`]. This makes it suitable for GUI and async
+/// embeddings and for debuggers.
+///
+/// All [`Settings`] are honored just like in [`SourceCode::run_with()`]. The timeout is counted
+/// from the creation of the machine.
+/// Like [`SourceCode::run_observed()`], a machine created with [`Machine::with_observer()`]
+/// reports every executed instruction and its effects to an [`Observer`].
+pub struct Machine<M, B = ()> {
+    program: Vec<Op>,
+    /// Source position of every instruction of `program`
+    spans: Vec<Span>,
+    pc: usize,
+    band: M,
+    execution: Execution,
+    input: VecDeque<u8>,
+    input_closed: bool,
+    output: Vec<u8>,
+    observer: B,
+    /// Iterations of the loops the program is currently in, only tracked for active observers
+    loop_iterations: Vec<u64>,
+    history: Option<History>,
+}
+
+impl<M: MemoryBand> Machine<M> {
+    /// Creates a machine that runs `code` on `band`
+    pub fn new(code: &SourceCode, band: M, settings: Settings) -> Machine<M> {
+        Machine::with_observer(code, band, settings, ())
+    }
+}
+
+impl<M: MemoryBand, B: Observer> Machine<M, B> {
+    /// Creates a machine that runs `code` on `band`, reporting to `observer`
+    pub fn with_observer(code: &SourceCode, band: M, settings: Settings, observer: B) -> Machine<M, B> {
+        let mut program = Vec::new();
+        let mut spans = Vec::new();
+        compile(code, &mut program, &mut spans);
+        Machine {
+            program,
+            spans,
+            pc: 0,
+            band,
+            execution: Execution::new(settings),
+            input: VecDeque::new(),
+            input_closed: false,
+            output: Vec::new(),
+            observer,
+            loop_iterations: Vec::new(),
+            history: None,
+        }
+    }
+
+    /// Starts recording the executed instructions, so they can be undone with
+    /// [`Machine::step_back()`].
+    ///
+    /// The latest instructions are kept in an undo log, earlier states are restored from
+    /// snapshots of the memoryband taken periodically, whose interval grows with the length of
+    /// the execution to bound the memory used. Changes made through [`Machine::band_mut()`] are
+    /// not recorded.
+    pub fn record_history(&mut self) {
+        self.record_history_with(HISTORY_LIMIT, CHECKPOINT_INTERVAL);
+    }
+
+    fn record_history_with(&mut self, log_limit: usize, interval: u64) {
+        self.history = Some(History {
+            log: VecDeque::new(),
+            log_limit,
+            checkpoints: Vec::new(),
+            interval,
+            consumed_input: Vec::new(),
+        });
+        self.checkpoint();
+    }
+
+    /// Undoes the last executed instruction. Returns false if there is no recorded instruction
+    /// to undo.
+    ///
+    /// Going back further than the undo log reaches replays the program from a checkpoint, so
+    /// the observer sees those instructions again.
+    pub fn step_back(&mut self) -> bool {
+        let steps = self.execution.steps;
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return false,
+        };
+        let undo = match history.log.pop_back() {
+            Some(undo) => undo,
+            None => return steps > 0 && self.replay_to(steps - 1),
+        };
+        while history.checkpoints.last().is_some_and(|checkpoint| checkpoint.steps >= steps) {
+            history.checkpoints.pop();
+        }
+        match self.program[undo.pc] {
+            Op::Move(i) => self.band.move_head(-i),
+            Op::Add(i) => self.band.add(i.wrapping_neg()),
+            Op::Print => {
+                self.output.pop();
+                self.execution.output_bytes -= 1;
+            }
+            Op::Read => {
+                self.band.write(undo.before);
+                if undo.consumed_input {
+                    if let Some(byte) = history.consumed_input.pop() {
+                        self.input.push_front(byte);
+                    }
+                }
+            }
+            _ => (),
+        }
+        self.pc = undo.pc;
+        self.execution.steps -= 1;
+        true
+    }
+
+    /// Restores the latest checkpoint before `target` steps and executes the program from there
+    /// until `target` steps are reached. Returns false if there is no such checkpoint.
+    fn replay_to(&mut self, target: u64) -> bool {
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return false,
+        };
+        let i = match history.checkpoints.iter().rposition(|checkpoint| checkpoint.steps <= target) {
+            Some(i) => i,
+            None => return false,
+        };
+        history.checkpoints.truncate(i + 1);
+        let checkpoint = &history.checkpoints[i];
+        self.band = match M::restore(&checkpoint.band) {
+            Ok(band) => band,
+            Err(_) => return false,
+        };
+        for byte in history.consumed_input.drain(checkpoint.consumed_input..).rev() {
+            self.input.push_front(byte);
+        }
+        self.pc = checkpoint.pc;
+        self.execution.steps = checkpoint.steps;
+        self.execution.output_bytes = checkpoint.output_bytes;
+        self.loop_iterations.clear();
+
+        // the output up to the target was produced before and may already be taken
+        let output = std::mem::take(&mut self.output);
+        while self.execution.steps < target {
+            let steps = self.execution.steps;
+            if self.execute().is_err() || self.execution.steps == steps {
+                break;
+            }
+        }
+        self.output = output;
+        if let Some(Op::Print) = self.program.get(self.pc) {
+            self.output.pop();
+        }
+        self.execution.steps == target
+    }
+
+    /// Adds a checkpoint of the current state to the history, dropping every second checkpoint
+    /// if there are too many
+    fn checkpoint(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.checkpoints.push(Checkpoint {
+                steps: self.execution.steps,
+                pc: self.pc,
+                band: self.band.snapshot(),
+                consumed_input: history.consumed_input.len(),
+                output_bytes: self.execution.output_bytes,
+            });
+            if history.checkpoints.len() > MAX_CHECKPOINTS {
+                let mut i = 0;
+                history.checkpoints.retain(|_| {
+                    i += 1;
+                    i % 2 == 1
+                });
+                history.interval *= 2;
+            }
+        }
+    }
+
+    /// Appends `bytes` to the input that is read by `,`
+    pub fn feed_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// Marks the end of the input. Once the fed input is used up, `,` applies the
+    /// [`super::settings::EofPolicy`] instead of pausing.
+    pub fn close_input(&mut self) {
+        self.input_closed = true;
+    }
+
+    /// Removes and returns the output produced so far
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Executes a single instruction
+    pub fn step(&mut self) -> Status {
+        match self.execute() {
+            Ok(status) => status,
+            Err(e) => Status::Error(e),
+        }
+    }
+
+    /// Executes up to `n` instructions, stopping early if the machine stops running
+    pub fn run_for(&mut self, n: u64) -> Status {
+        for _ in 0..n {
+            match self.step() {
+                Status::Running => (),
+                status => return status,
+            }
+        }
+        self.status()
+    }
+
+    /// Executes instructions until `predicate` returns true for the machine or it stops running.
+    /// The predicate is checked before every instruction.
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Status
+    where F: FnMut(&Machine<M, B>) -> bool {
+        while !predicate(self) {
+            match self.step() {
+                Status::Running => (),
+                status => return status,
+            }
+        }
+        self.status()
+    }
+
+    /// Executes instructions until the machine stops running
+    pub fn run(&mut self) -> Status {
+        self.run_until(|_| false)
+    }
+
+    /// The status of the machine without executing anything
+    pub fn status(&self) -> Status {
+        match self.program.get(self.pc) {
+            None => Status::Halted,
+            Some(Op::Read) if self.input.is_empty() && !self.input_closed => Status::NeedsInput,
+            Some(_) => Status::Running,
+        }
+    }
+
+    /// Index of the next instruction to execute
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Number of instructions of the program
+    pub fn len(&self) -> usize {
+        self.program.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.program.is_empty()
+    }
+
+    /// Source position of the instruction at index `pc`, the brackets of a loop are separate
+    /// instructions
+    pub fn span_at(&self, pc: usize) -> Option<Span> {
+        self.spans.get(pc).copied()
+    }
+
+    /// Index of the first instruction at or behind byte `offset` of the source code
+    pub fn pc_at(&self, offset: usize) -> Option<usize> {
+        self.spans.iter().position(|span| span.end > offset)
+    }
+
+    /// The instruction at index `pc`
+    pub fn instruction_at(&self, pc: usize) -> Option<Instruction> {
+        self.program.get(pc).map(|op| match *op {
+            Op::Move(i) => Instruction::Move(i),
+            Op::Add(i) => Instruction::Add(i),
+            Op::Print => Instruction::Print,
+            Op::Read => Instruction::Read,
+            Op::LoopStart(_) => Instruction::LoopStart,
+            Op::LoopEnd(_) => Instruction::LoopEnd,
+            Op::Debug => Instruction::Debug,
+        })
+    }
+
+    /// If the next instruction starts a loop, the index of the instruction behind that loop
+    pub fn loop_exit(&self) -> Option<usize> {
+        match self.program.get(self.pc) {
+            Some(Op::LoopStart(end)) => Some(end + 1),
+            _ => None,
+        }
+    }
+
+    /// Number of instructions executed so far
+    pub fn steps(&self) -> u64 {
+        self.execution.steps
+    }
+
+    pub fn band(&self) -> &M {
+        &self.band
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.execution.settings
+    }
+
+    pub fn band_mut(&mut self) -> &mut M {
+        &mut self.band
+    }
+
+    pub fn observer(&self) -> &B {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut B {
+        &mut self.observer
+    }
+
+    /// Consumes the machine, returning its memoryband
+    pub fn into_band(self) -> M {
+        self.band
+    }
+
+    /// Span of the loop whose brackets are the instructions at `start` and `end`
+    fn loop_span(&self, start: usize, end: usize) -> Span {
+        Span { start: self.spans[start].start, end: self.spans[end].end }
+    }
+
+    fn execute(&mut self) -> Result<Status, RuntimeError> {
+        let op = match self.program.get(self.pc) {
+            Some(op) => *op,
+            None => return Ok(Status::Halted),
+        };
+        let pc = self.pc;
+        let before = if B::ACTIVE || self.history.is_some() { self.band.read() } else { 0 };
+        let mut exited = None;
+        let mut consumed = None;
+        let instruction = match op {
+            Op::Move(i) => {
+                self.execution.check_step()?;
+                self.execution.move_head(&mut self.band, i)?;
+                if B::ACTIVE {
+                    let to = self.band.position();
+                    self.observer.head_moved(to - i, to);
+                }
+                Instruction::Move(i)
+            }
+            Op::Add(i) => {
+                self.execution.check_step()?;
+                self.band.add(i);
+                if B::ACTIVE {
+                    self.observer.cell_written(self.band.position(), before, self.band.read());
+                }
+                Instruction::Add(i)
+            }
+            Op::Print => {
+                self.execution.check_step()?;
+                self.execution.output()?;
+                let byte = self.band.read();
+                self.output.push(byte);
+                if B::ACTIVE {
+                    self.observer.cell_read(self.band.position(), byte);
+                    self.observer.output(byte);
+                }
+                Instruction::Print
+            }
+            Op::Read => {
+                if self.input.is_empty() && !self.input_closed {
+                    return Ok(Status::NeedsInput);
+                }
+                self.execution.check_step()?;
+                let byte = self.input.pop_front();
+                consumed = byte;
+                if B::ACTIVE {
+                    self.observer.input(byte);
+                }
+                match byte {
+                    Some(c) => self.band.write(c),
+                    None => self.execution.settings.eof.apply(&mut self.band)?,
+                }
+                if B::ACTIVE {
+                    self.observer.cell_written(self.band.position(), before, self.band.read());
+                }
+                Instruction::Read
+            }
+            Op::LoopStart(end) => {
+                self.execution.check_step()?;
+                if B::ACTIVE {
+                    self.observer.loop_entered(self.loop_span(pc, end));
+                    self.observer.cell_read(self.band.position(), before);
+                }
+                if self.band.read() == 0 {
+                    if B::ACTIVE {
+                        exited = Some((self.loop_span(pc, end), 0));
+                    }
+                    self.pc = end;
+                } else if B::ACTIVE {
+                    self.loop_iterations.push(0);
+                }
+                Instruction::LoopStart
+            }
+            Op::LoopEnd(start) => {
+                self.execution.back_edge()?;
+                self.execution.check_step()?;
+                if B::ACTIVE {
+                    self.observer.cell_read(self.band.position(), before);
+                    if let Some(iterations) = self.loop_iterations.last_mut() {
+                        *iterations += 1;
+                    }
+                }
+                if self.band.read() != 0 {
+                    self.pc = start;
+                } else if B::ACTIVE {
+                    let iterations = self.loop_iterations.pop().unwrap_or(0);
+                    exited = Some((self.loop_span(start, pc), iterations));
+                }
+                Instruction::LoopEnd
+            }
+            Op::Debug => {
+                self.execution.check_step()?;
+                self.execution.dump(&self.band);
+                Instruction::Debug
+            }
+        };
+        self.execution.count_step();
+        if B::ACTIVE {
+            self.observer.instruction(instruction, self.spans[pc], &self.band, before);
+            if let Some((span, iterations)) = exited {
+                self.observer.loop_exited(span, iterations);
+            }
+        }
+        self.pc += 1;
+        if let Some(history) = &mut self.history {
+            history.log.push_back(Undo { pc, before, consumed_input: consumed.is_some() });
+            if history.log.len() > history.log_limit {
+                history.log.pop_front();
+            }
+            history.consumed_input.extend(consumed);
+            if self.execution.steps.is_multiple_of(history.interval) {
+                self.checkpoint();
+            }
+        }
+        Ok(self.status())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::InfiniteMemoryBand;
+    use crate::settings::EofPolicy;
+    use crate::observer::run_observed;
+    use crate::profile::Profiler;
+
+    fn machine(code: &str, settings: Settings) -> Machine<InfiniteMemoryBand> {
+        let code = code.parse::<SourceCode>().unwrap();
+        Machine::new(&code, InfiniteMemoryBand::new(), settings)
+    }
+
+    #[test]
+    fn test_compile() {
+        let code = "+[>[-]<]".parse::<SourceCode>().unwrap();
+        let mut ops = Vec::new();
+        let mut spans = Vec::new();
+        compile(&code, &mut ops, &mut spans);
+
+        assert_eq!(ops, vec![
+            Op::Add(1),
+            Op::LoopStart(7),
+            Op::Move(1),
+            Op::LoopStart(5),
+            Op::Add(u8::MAX),
+            Op::LoopEnd(3),
+            Op::Move(-1),
+            Op::LoopEnd(1),
+        ]);
+        let starts: Vec<usize> = spans.iter().map(|span| span.start).collect();
+        assert_eq!(starts, vec![0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_navigation() {
+        let m = machine("+ [>]\n.", Settings::default());
+        assert_eq!(m.len(), 5);
+        assert_eq!(m.pc_at(1), Some(1));
+        assert_eq!(m.pc_at(5), Some(4));
+        assert_eq!(m.pc_at(7), None);
+        assert_eq!(m.span_at(4), Some(Span { start: 6, end: 7 }));
+        assert_eq!(m.loop_exit(), None);
+
+        let mut m = m;
+        m.step();
+        assert_eq!(m.loop_exit(), Some(4));
+    }
+
+    #[test]
+    fn test_run_matches_sourcecode() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let mut m = machine(source, Settings::default());
+        assert!(matches!(m.run(), Status::Halted));
+
+        let mut expected: Vec<u8> = Vec::new();
+        source.parse::<SourceCode>().unwrap()
+            .run::<_, _, InfiniteMemoryBand>(&mut std::iter::empty(), &mut expected).unwrap();
+        assert_eq!(m.take_output(), expected);
+        assert_eq!(expected, b"Hello World!\n");
+    }
+
+    #[test]
+    fn test_observer_matches_sourcecode() {
+        let source = "++[>+++[>+<-]<-]>>.";
+        let code = source.parse::<SourceCode>().unwrap();
+        let mut m = Machine::with_observer(&code, InfiniteMemoryBand::new(), Settings::default(), Profiler::new(source, &code));
+        assert!(matches!(m.run(), Status::Halted));
+
+        let mut expected = Profiler::new(source, &code);
+        run_observed(&code, &mut InfiniteMemoryBand::new(), &[], &mut expected);
+        assert_eq!(m.observer().counts(), expected.counts());
+        assert_eq!(m.observer().loops(), expected.loops());
+    }
+
+    /// Cells around the start, head position, pc, steps and output of a machine
+    fn state(m: &mut Machine<InfiniteMemoryBand>) -> (Vec<Option<u8>>, isize, usize, u64, Vec<u8>) {
+        let position = m.band().position();
+        let cells = (-2..4).map(|cell| m.band().peek(cell - position)).collect();
+        (cells, position, m.pc(), m.steps(), m.take_output())
+    }
+
+    #[test]
+    fn test_step_back() {
+        let source = ",[>+++.<-]>[<+>-]<.";
+        let mut m = machine(source, Settings::default());
+        m.feed_input(&[3]);
+        m.run();
+        let mut expected = Vec::new();
+        for n in 0..=m.steps() {
+            let mut m = machine(source, Settings::default());
+            m.feed_input(&[3]);
+            m.run_for(n);
+            expected.push(state(&mut m));
+        }
+
+        for (log_limit, interval) in [(HISTORY_LIMIT, CHECKPOINT_INTERVAL), (4, 3)] {
+            let mut m = machine(source, Settings::default());
+            m.record_history_with(log_limit, interval);
+            m.feed_input(&[3]);
+            assert!(matches!(m.run(), Status::Halted));
+            let output = m.take_output();
+            assert_eq!(output.len(), 4);
+            let steps = m.steps() as usize;
+
+            for n in (0..steps).rev() {
+                assert!(m.step_back());
+                let (band, position, pc, steps, _) = state(&mut m);
+                assert_eq!((band, position, pc, steps), {
+                    let (band, position, pc, steps, _) = expected[n].clone();
+                    (band, position, pc, steps)
+                });
+            }
+            assert!(!m.step_back());
+            assert_eq!(m.band().read(), 0);
+
+            // the input is consumed again and the output produced again
+            assert!(matches!(m.run(), Status::Halted));
+            assert_eq!(m.take_output(), output);
+        }
+    }
+
+    #[test]
+    fn test_step_back_after_error() {
+        let settings = Settings { max_memory: Some(3), ..Settings::default() };
+        let mut m = machine("+>+>+>+", settings);
+        m.record_history();
+
+        assert!(matches!(m.run(), Status::Error(RuntimeError::MemoryLimit { max_cells: 3 })));
+        assert_eq!((m.pc(), m.steps()), (5, 5));
+        assert!(m.step_back());
+        assert_eq!((m.pc(), m.steps()), (4, 4));
+        assert_eq!((m.band().position(), m.band().read()), (2, 0));
+        assert!(m.replay_to(2));
+        assert_eq!((m.pc(), m.band().position()), (2, 1));
+    }
+
+    #[test]
+    fn test_checkpoints_are_thinned_out() {
+        let mut m = machine("+[+]", Settings::default());
+        m.record_history_with(10, 1);
+        m.run_for(MAX_CHECKPOINTS as u64 * 3);
+        let history = m.history.as_ref().unwrap();
+        assert!(history.checkpoints.len() <= MAX_CHECKPOINTS);
+        assert_eq!(history.checkpoints[0].steps, 0);
+        assert_eq!(history.log.len(), 10);
+
+        while m.step_back() {}
+        assert_eq!(m.steps(), 0);
+    }
+
+    #[test]
+    fn test_needs_input() {
+        let mut m = machine(",.,.", Settings::default());
+        assert!(matches!(m.step(), Status::NeedsInput));
+        assert_eq!(m.pc(), 0);
+
+        m.feed_input(b"a");
+        assert!(matches!(m.run(), Status::NeedsInput));
+        assert_eq!(m.take_output(), b"a");
+
+        m.close_input();
+        assert!(matches!(m.run(), Status::Halted));
+        assert_eq!(m.take_output(), vec![0]);
+    }
+
+    #[test]
+    fn test_eof_policy() {
+        let settings = Settings { eof: EofPolicy::Error, ..Settings::default() };
+        let mut m = machine("+,", settings);
+        m.close_input();
+
+        assert!(matches!(m.run(), Status::Error(RuntimeError::UnexpectedEof)));
+        assert_eq!(m.band().read(), 1);
+    }
+
+    #[test]
+    fn test_run_for() {
+        let mut m = machine("+>+>+", Settings::default());
+        assert!(matches!(m.run_for(2), Status::Running));
+        assert_eq!(m.pc(), 2);
+        assert!(matches!(m.run_for(5), Status::Halted));
+        assert_eq!(m.steps(), 5);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut m = machine("+[+]", Settings::default());
+        assert!(matches!(m.run_until(|m| m.band().read() == 100), Status::Running));
+        assert_eq!(m.band().read(), 100);
+    }
+
+    #[test]
+    fn test_budget_matches_sourcecode() {
+        let settings = Settings { max_steps: Some(10), ..Settings::default() };
+        let mut m = machine("+[]", settings);
+
+        assert!(matches!(m.run(), Status::Error(RuntimeError::BudgetExceeded { steps: 10, .. })));
+    }
+}
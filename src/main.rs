@@ -1,9 +1,13 @@
 use rsbrainfuck::args::*;
 //use std::env;
-use std::error::Error;
+use std::process;
 use structopt::StructOpt;
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() {
     let args = Args::from_args();
-    rsbrainfuck::run(args)
+    // the errors describe themselves for users, their Debug form is meant for developers
+    if let Err(e) = rsbrainfuck::run(args) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
 }
 
@@ -1,3 +1,5 @@
+use super::snapshot::MemorySnapshot;
+use super::error::RuntimeError;
 use std::collections::VecDeque;
 use std::cmp::Ordering::*;
 
@@ -21,6 +23,42 @@ pub trait MemoryBand {
     /// Moves the reading head left by `moves` amount.
     /// Positive values correspond to moving right, negative values to moving left.
     fn move_head(&mut self, moves: isize);
+    /// Position of the reading head, counted in cells right of the cell it started on
+    fn position(&self) -> isize;
+    /// Index of the cell the reading head started on, counted from the leftmost cell like the
+    /// cells of a [`MemoryBand::snapshot()`]
+    fn origin_index(&self) -> usize;
+    /// Reads the cell `offset` cells right of the reading head without moving it.
+    /// Returns `None` if there is no such cell.
+    fn peek(&self, offset: isize) -> Option<u8>;
+    /// Writes `int` to the cell `offset` cells right of the reading head without moving it
+    fn poke(&mut self, offset: isize, int: u8) {
+        self.move_head(offset);
+        self.write(int);
+        self.move_head(-offset);
+    }
+    /// Moves the reading head like [`MemoryBand::move_head()`], but fails with
+    /// [`RuntimeError::MemoryLimit`] instead if the band would have to grow beyond `max_cells`
+    /// cells. The default implementation is meant for bands that never grow and ignores the limit.
+    fn move_head_within(&mut self, moves: isize, max_cells: usize) -> Result<(), RuntimeError> {
+        let _ = max_cells;
+        self.move_head(moves);
+        Ok(())
+    }
+    /// Writes `int` like [`MemoryBand::poke()`], but fails with [`RuntimeError::MemoryLimit`]
+    /// instead if the band would have to grow beyond `max_cells` cells
+    fn poke_within(&mut self, offset: isize, int: u8, max_cells: usize) -> Result<(), RuntimeError> {
+        self.move_head_within(offset, max_cells)?;
+        self.write(int);
+        self.move_head(-offset);
+        Ok(())
+    }
+    /// Captures the contents of all cells and the position of the reading head
+    fn snapshot(&self) -> MemorySnapshot;
+    /// Creates a Memoryband instance holding the state captured in `snapshot`.
+    /// Fails if the snapshot does not fit into the memoryband.
+    fn restore(snapshot: &MemorySnapshot) -> Result<Self, String>
+    where Self: Sized;
 }
 
 
@@ -30,6 +68,8 @@ pub trait MemoryBand {
 pub struct InfiniteMemoryBand {
     band: VecDeque<u8>,
     current_index: usize,
+    /// Index of the cell the reading head started on
+    origin: usize,
 }
 
 impl InfiniteMemoryBand {
@@ -51,6 +91,7 @@ impl InfiniteMemoryBand {
             for _ in 0..moves {
                 self.band.push_front(0);
             }
+            self.origin += moves;
             return;
         } else if moves > self.current_index {
             let moves_later = moves - self.current_index;
@@ -67,6 +108,7 @@ impl MemoryBand for InfiniteMemoryBand {
         InfiniteMemoryBand {
             band: vec![0].into_iter().collect(),
             current_index: 0,
+            origin: 0,
         }
     }
 
@@ -94,6 +136,58 @@ impl MemoryBand for InfiniteMemoryBand {
             _ => (),
         }
     }
+
+    /// Position of the reading head, counted in cells right of the cell it started on
+    fn position(&self) -> isize {
+        self.current_index as isize - self.origin as isize
+    }
+
+    /// Index of the cell the reading head started on, which grows with every cell added to the
+    /// left
+    fn origin_index(&self) -> usize {
+        self.origin
+    }
+
+    /// Reads the cell `offset` cells right of the reading head without moving it.
+    /// Cells that were never visited hold `0`.
+    fn peek(&self, offset: isize) -> Option<u8> {
+        // there is no cell beyond the largest index
+        let index = (self.current_index as isize).checked_add(offset)?;
+        if index < 0 {
+            return Some(0);
+        }
+        Some(self.band.get(index as usize).copied().unwrap_or(0))
+    }
+
+    /// Moves the reading head like [`MemoryBand::move_head()`], unless the band would have to
+    /// grow beyond `max_cells` cells
+    fn move_head_within(&mut self, moves: isize, max_cells: usize) -> Result<(), RuntimeError> {
+        let needed = if moves < 0 {
+            self.band.len().saturating_add(moves.unsigned_abs().saturating_sub(self.current_index))
+        } else {
+            self.band.len().max(self.current_index.saturating_add(moves as usize).saturating_add(1))
+        };
+        if needed > max_cells {
+            return Err(RuntimeError::MemoryLimit { max_cells });
+        }
+        self.move_head(moves);
+        Ok(())
+    }
+
+    /// Captures the contents of all cells and the position of the reading head
+    fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot::with_origin(self.band.iter().copied().collect(), self.current_index, self.origin)
+            .expect("the reading head and the origin always point into the band")
+    }
+
+    /// Creates a Memoryband instance holding the state captured in `snapshot`
+    fn restore(snapshot: &MemorySnapshot) -> Result<InfiniteMemoryBand, String> {
+        Ok(InfiniteMemoryBand {
+            band: snapshot.cells().iter().copied().collect(),
+            current_index: snapshot.head(),
+            origin: snapshot.origin(),
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -143,6 +237,46 @@ impl MemoryBand for FiniteMemoryBand {
             _ => (),
         }
     }
+
+    fn position(&self) -> isize {
+        self.current_index as isize - 15_000
+    }
+
+    fn origin_index(&self) -> usize {
+        15_000
+    }
+
+    fn peek(&self, offset: isize) -> Option<u8> {
+        let index = (self.current_index as isize).checked_add(offset)?;
+        if index < 0 {
+            return None;
+        }
+        self.band.get(index as usize).copied()
+    }
+
+    fn snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot::with_origin(self.band.to_vec(), self.current_index, 15_000)
+            .expect("the reading head and the origin always point into the band")
+    }
+
+    /// The cells of `snapshot` are placed so that its origin ends up at the usual starting
+    /// index, unless that would push cells past the end of the band.
+    fn restore(snapshot: &MemorySnapshot) -> Result<FiniteMemoryBand, String> {
+        let cells = snapshot.cells();
+        if cells.len() > 30_000 {
+            return Err(format!(
+                "The snapshot holds {} cells, but the band only has 30000.",
+                cells.len()
+            ));
+        }
+        let offset = (15_000usize.saturating_sub(snapshot.origin())).min(30_000 - cells.len());
+        let mut band = [0; 30_000];
+        band[offset..offset + cells.len()].copy_from_slice(cells);
+        Ok(FiniteMemoryBand {
+            band,
+            current_index: offset + snapshot.head(),
+        })
+    }
 }
 
 
@@ -158,6 +292,7 @@ mod test_infinite {
         let expected = InfiniteMemoryBand {
             band: vec![0].into_iter().collect(),
             current_index: 0,
+            origin: 0,
         };
         assert_eq!(band, expected);
     }
@@ -167,6 +302,7 @@ mod test_infinite {
         let band = InfiniteMemoryBand {
             band: vec![1, 2].into_iter().collect(),
             current_index: 1,
+            origin: 0,
         };
         assert_eq!(2, band.read());
     }
@@ -176,12 +312,14 @@ mod test_infinite {
         let mut band = InfiniteMemoryBand {
             band: vec![1, 2].into_iter().collect(),
             current_index: 1,
+            origin: 0,
         };
         band.write(NEG1-4); // -5
 
         let expected = InfiniteMemoryBand {
             band: vec![1, NEG1-4].into_iter().collect(),
             current_index: 1,
+            origin: 0,
         };
 
         assert_eq!(band, expected);
@@ -195,6 +333,7 @@ mod test_infinite {
         let expected = InfiniteMemoryBand {
             band: vec![NEG1].into_iter().collect(),
             current_index: 0,
+            origin: 0,
         };
 
         assert_eq!(band, expected);
@@ -209,6 +348,7 @@ mod test_infinite {
         let expected = InfiniteMemoryBand {
             band: vec![4, 0, 0].into_iter().collect(),
             current_index: 2,
+            origin: 0,
         };
         assert_eq!(band, expected);
     }
@@ -223,9 +363,69 @@ mod test_infinite {
         let expected = InfiniteMemoryBand {
             band: vec![0, 0, 4, 0].into_iter().collect(),
             current_index: 0,
+            origin: 2,
         };
         assert_eq!(band, expected);
     }
+
+    #[test]
+    fn test_move_head_within() {
+        let mut band = InfiniteMemoryBand::new();
+        band.move_head_within(2, 3).unwrap();
+        band.move_head_within(-4, 5).unwrap();
+        assert_eq!(band.band.len(), 5);
+        assert_eq!(band.current_index, 0);
+
+        assert!(matches!(band.move_head_within(-1, 5), Err(RuntimeError::MemoryLimit { max_cells: 5 })));
+        assert!(matches!(band.move_head_within(5, 5), Err(RuntimeError::MemoryLimit { max_cells: 5 })));
+        band.move_head_within(4, 5).unwrap();
+        assert_eq!(band.current_index, 4);
+        assert_eq!(band.band.len(), 5);
+    }
+
+    #[test]
+    fn test_position() {
+        let mut band = InfiniteMemoryBand::new();
+        band.move_head(-3);
+        band.move_head(1);
+        assert_eq!(band.position(), -2);
+        band.move_head(5);
+        assert_eq!(band.position(), 3);
+    }
+
+    #[test]
+    fn test_peek_poke() {
+        let mut band = InfiniteMemoryBand::new();
+        band.poke(-2, 7);
+        band.poke(1, 3);
+
+        assert_eq!(band.peek(-2), Some(7));
+        assert_eq!(band.peek(0), Some(0));
+        assert_eq!(band.peek(1), Some(3));
+        assert_eq!(band.peek(-50), Some(0));
+        assert_eq!(band.peek(50), Some(0));
+        assert_eq!(band.read(), 0);
+    }
+
+    #[test]
+    fn test_peek_far_away() {
+        let mut band = InfiniteMemoryBand::new();
+        band.move_head(1);
+
+        assert_eq!(band.peek(isize::MAX), None);
+        assert_eq!(band.peek(isize::MIN), Some(0));
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut band = InfiniteMemoryBand::new();
+        band.write(4);
+        band.move_head(-2);
+        band.write(9);
+
+        let restored = InfiniteMemoryBand::restore(&band.snapshot()).unwrap();
+        assert_eq!(restored, band);
+    }
 }
 
 #[cfg(test)]
@@ -273,4 +473,49 @@ mod test_finite {
 
         assert_eq!(band.read(), 50);
     }
+
+    #[test]
+    fn position() {
+        let mut band = FiniteMemoryBand::new();
+        band.move_head(-3);
+        assert_eq!(band.position(), -3);
+    }
+
+    #[test]
+    fn peek_poke() {
+        let mut band = FiniteMemoryBand::new();
+        band.poke(-1, 4);
+
+        assert_eq!(band.peek(-1), Some(4));
+        assert_eq!(band.peek(14_999), Some(0));
+        assert_eq!(band.peek(15_000), None);
+        assert_eq!(band.peek(-15_001), None);
+        assert_eq!(band.peek(isize::MAX), None);
+        assert_eq!(band.peek(isize::MIN), None);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut band = FiniteMemoryBand::new();
+        band.move_head(-4);
+        band.write(12);
+
+        let restored = FiniteMemoryBand::restore(&band.snapshot()).unwrap();
+        assert_eq!(restored, band);
+    }
+
+    #[test]
+    fn restore_small_snapshot() {
+        let snapshot = MemorySnapshot::new(vec![1, 2, 3], 1).unwrap();
+        let band = FiniteMemoryBand::restore(&snapshot).unwrap();
+
+        assert_eq!(band.current_index, 15_000);
+        assert_eq!(band.band[14_999..15_002], [1, 2, 3]);
+    }
+
+    #[test]
+    fn restore_too_large_snapshot() {
+        let snapshot = MemorySnapshot::new(vec![0; 30_001], 0).unwrap();
+        assert!(FiniteMemoryBand::restore(&snapshot).is_err());
+    }
 }
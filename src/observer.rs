@@ -0,0 +1,287 @@
+use super::memoryband::MemoryBand;
+use super::sourcecode::Span;
+
+/// An executed instruction as reported to an observer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Move(isize),
+    Add(u8),
+    Print,
+    Read,
+    /// Evaluation of a loop condition when the loop is entered
+    LoopStart,
+    /// Evaluation of a loop condition after an iteration
+    LoopEnd,
+    Debug,
+}
+
+impl Instruction {
+    /// Lowercase name of the kind of instruction, loop starts and ends are both called `loop`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Instruction::Move(_) => "move",
+            Instruction::Add(_) => "add",
+            Instruction::Print => "print",
+            Instruction::Read => "read",
+            Instruction::LoopStart | Instruction::LoopEnd => "loop",
+            Instruction::Debug => "debug",
+        }
+    }
+}
+
+/// Hooks the interpreter calls while it executes a program, see
+/// [`super::sourcecode::SourceCode::run_observed()`].
+///
+/// All hooks do nothing by default, so an observer only implements the events it is interested in.
+/// Positions are head positions relative to the cell the head started on, like
+/// [`MemoryBand::position()`].
+/// The interpreter is generic over its observer, and the no-op observer `()` sets
+/// [`Observer::ACTIVE`] to false, so a run without observer pays nothing for the hooks.
+/// Several observers can be combined as a tuple, and `Option` enables an observer at runtime.
+pub trait Observer {
+    /// Whether the observer wants to be called at all
+    const ACTIVE: bool = true;
+
+    /// Called after every executed instruction with the span of the source code it was parsed
+    /// from, the memoryband after the execution and the value of the current cell before it.
+    /// The span of a loop condition is the span of its bracket.
+    fn instruction<M: MemoryBand>(&mut self, _instruction: Instruction, _span: Span, _band: &M, _before: u8) {}
+
+    /// Called when the execution reaches the loop spanning `span`, before its condition is checked
+    fn loop_entered(&mut self, _span: Span) {}
+
+    /// Called when the execution leaves the loop spanning `span` after `iterations` iterations
+    fn loop_exited(&mut self, _span: Span, _iterations: u64) {}
+
+    /// Called when a loop condition or a `.` reads the cell at `position`
+    fn cell_read(&mut self, _position: isize, _value: u8) {}
+
+    /// Called when a `+`, `-` or `,` writes the cell at `position`
+    fn cell_written(&mut self, _position: isize, _before: u8, _after: u8) {}
+
+    /// Called when the head moved from position `from` to `to`
+    fn head_moved(&mut self, _from: isize, _to: isize) {}
+
+    /// Called when a `,` consumed `byte` from the input, or found no input if it is `None`
+    fn input(&mut self, _byte: Option<u8>) {}
+
+    /// Called when a `.` produced `byte`
+    fn output(&mut self, _byte: u8) {}
+}
+
+impl Observer for () {
+    const ACTIVE: bool = false;
+}
+
+impl<B: Observer + ?Sized> Observer for &mut B {
+    const ACTIVE: bool = B::ACTIVE;
+
+    fn instruction<M: MemoryBand>(&mut self, instruction: Instruction, span: Span, band: &M, before: u8) {
+        (**self).instruction(instruction, span, band, before)
+    }
+
+    fn loop_entered(&mut self, span: Span) {
+        (**self).loop_entered(span)
+    }
+
+    fn loop_exited(&mut self, span: Span, iterations: u64) {
+        (**self).loop_exited(span, iterations)
+    }
+
+    fn cell_read(&mut self, position: isize, value: u8) {
+        (**self).cell_read(position, value)
+    }
+
+    fn cell_written(&mut self, position: isize, before: u8, after: u8) {
+        (**self).cell_written(position, before, after)
+    }
+
+    fn head_moved(&mut self, from: isize, to: isize) {
+        (**self).head_moved(from, to)
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        (**self).input(byte)
+    }
+
+    fn output(&mut self, byte: u8) {
+        (**self).output(byte)
+    }
+}
+
+impl<B: Observer> Observer for Option<B> {
+    const ACTIVE: bool = B::ACTIVE;
+
+    fn instruction<M: MemoryBand>(&mut self, instruction: Instruction, span: Span, band: &M, before: u8) {
+        if let Some(observer) = self {
+            observer.instruction(instruction, span, band, before)
+        }
+    }
+
+    fn loop_entered(&mut self, span: Span) {
+        if let Some(observer) = self {
+            observer.loop_entered(span)
+        }
+    }
+
+    fn loop_exited(&mut self, span: Span, iterations: u64) {
+        if let Some(observer) = self {
+            observer.loop_exited(span, iterations)
+        }
+    }
+
+    fn cell_read(&mut self, position: isize, value: u8) {
+        if let Some(observer) = self {
+            observer.cell_read(position, value)
+        }
+    }
+
+    fn cell_written(&mut self, position: isize, before: u8, after: u8) {
+        if let Some(observer) = self {
+            observer.cell_written(position, before, after)
+        }
+    }
+
+    fn head_moved(&mut self, from: isize, to: isize) {
+        if let Some(observer) = self {
+            observer.head_moved(from, to)
+        }
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        if let Some(observer) = self {
+            observer.input(byte)
+        }
+    }
+
+    fn output(&mut self, byte: u8) {
+        if let Some(observer) = self {
+            observer.output(byte)
+        }
+    }
+}
+
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    const ACTIVE: bool = A::ACTIVE || B::ACTIVE;
+
+    fn instruction<M: MemoryBand>(&mut self, instruction: Instruction, span: Span, band: &M, before: u8) {
+        self.0.instruction(instruction, span, band, before);
+        self.1.instruction(instruction, span, band, before);
+    }
+
+    fn loop_entered(&mut self, span: Span) {
+        self.0.loop_entered(span);
+        self.1.loop_entered(span);
+    }
+
+    fn loop_exited(&mut self, span: Span, iterations: u64) {
+        self.0.loop_exited(span, iterations);
+        self.1.loop_exited(span, iterations);
+    }
+
+    fn cell_read(&mut self, position: isize, value: u8) {
+        self.0.cell_read(position, value);
+        self.1.cell_read(position, value);
+    }
+
+    fn cell_written(&mut self, position: isize, before: u8, after: u8) {
+        self.0.cell_written(position, before, after);
+        self.1.cell_written(position, before, after);
+    }
+
+    fn head_moved(&mut self, from: isize, to: isize) {
+        self.0.head_moved(from, to);
+        self.1.head_moved(from, to);
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        self.0.input(byte);
+        self.1.input(byte);
+    }
+
+    fn output(&mut self, byte: u8) {
+        self.0.output(byte);
+        self.1.output(byte);
+    }
+}
+
+/// Runs `code` with the default settings on `band`, reading `input` and discarding the output
+#[cfg(test)]
+pub(crate) fn run_observed<M: MemoryBand, O: Observer>(
+    code: &crate::sourcecode::SourceCode,
+    band: &mut M,
+    input: &[u8],
+    observer: &mut O,
+) {
+    code.run_observed(band, &mut input.iter().copied(), &mut Vec::<u8>::new(), &crate::settings::Settings::default(), observer)
+        .unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::InfiniteMemoryBand;
+    use crate::sourcecode::SourceCode;
+
+    /// Records the events as strings
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Observer for Events {
+        fn loop_entered(&mut self, span: Span) {
+            self.0.push(format!("enter {}..{}", span.start, span.end));
+        }
+
+        fn loop_exited(&mut self, span: Span, iterations: u64) {
+            self.0.push(format!("exit {}..{} {}", span.start, span.end, iterations));
+        }
+
+        fn cell_read(&mut self, position: isize, value: u8) {
+            self.0.push(format!("read {} {}", position, value));
+        }
+
+        fn cell_written(&mut self, position: isize, before: u8, after: u8) {
+            self.0.push(format!("write {} {} {}", position, before, after));
+        }
+
+        fn head_moved(&mut self, from: isize, to: isize) {
+            self.0.push(format!("move {} {}", from, to));
+        }
+
+        fn input(&mut self, byte: Option<u8>) {
+            self.0.push(format!("input {:?}", byte));
+        }
+
+        fn output(&mut self, byte: u8) {
+            self.0.push(format!("output {}", byte));
+        }
+    }
+
+    fn observe(source: &str, input: &[u8]) -> Vec<String> {
+        let code = source.parse::<SourceCode>().unwrap();
+        let mut events = Events::default();
+        run_observed(&code, &mut InfiniteMemoryBand::new(), input, &mut events);
+        events.0
+    }
+
+    #[test]
+    fn test_events() {
+        let events = observe(",[->+<]>.", &[1]);
+        assert_eq!(events, vec![
+            "input Some(1)", "write 0 0 1",
+            "enter 1..7", "read 0 1",
+            "write 0 1 0", "move 0 1", "write 1 0 1", "move 1 0",
+            "read 0 0", "exit 1..7 1",
+            "move 0 1", "read 1 1", "output 1",
+        ]);
+    }
+
+    #[test]
+    fn test_combined() {
+        let code = "+[-]".parse::<SourceCode>().unwrap();
+        let mut observers = (Events::default(), Some(Events::default()));
+        run_observed(&code, &mut InfiniteMemoryBand::new(), &[], &mut observers);
+        assert_eq!(observers.0 .0.len(), 6);
+        assert_eq!(observers.0 .0, observers.1.unwrap().0);
+    }
+}
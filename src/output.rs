@@ -1,17 +1,131 @@
+use std::io::{self, BufWriter, IsTerminal, Stdout, Write};
+
+/// Destination of the bytes written by the brainfuck `.` command.
+///
+/// Wrap any [`Write`] in [`WriteOutput`] to write to files, pipes or sockets.
 pub trait Output {
-    fn write_char(&mut self, c: char);
+    fn write_byte(&mut self, byte: u8) -> io::Result<()>;
+    /// Makes sure all bytes written so far reach their destination.
+    /// The interpreter calls this before every input request and at the end of a program.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
-pub struct StdOutput();
+/// Writes the raw bytes to stdout.
+///
+/// The output is buffered and only flushed when [`Output::flush()`] is called, or on every
+/// newline if stdout is attached to a terminal.
+pub struct StdOutput {
+    stdout: BufWriter<Stdout>,
+    line_buffered: bool,
+}
+
+impl StdOutput {
+    pub fn new() -> StdOutput {
+        let stdout = io::stdout();
+        StdOutput {
+            line_buffered: stdout.is_terminal(),
+            stdout: BufWriter::new(stdout),
+        }
+    }
+}
+
+impl Default for StdOutput {
+    fn default() -> StdOutput {
+        StdOutput::new()
+    }
+}
 
 impl Output for StdOutput {
-    fn write_char(&mut self, c: char) {
-        print!("{}", c);
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.stdout.write_all(&[byte])?;
+        if self.line_buffered && byte == b'\n' {
+            self.stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+impl Output for Vec<u8> {
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.push(byte);
+        Ok(())
     }
 }
 
+/// Collects each byte as the [`char`] with the same code point.
 impl Output for Vec<char> {
-    fn write_char(&mut self, c: char) {
-        self.push(c);
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.push(byte as char);
+        Ok(())
+    }
+}
+
+/// Adapter that writes the output to any [`Write`].
+///
+/// Every byte is handed to the writer immediately, wrap it in a [`io::BufWriter`] if it is slow
+/// to write to.
+pub struct WriteOutput<W>(pub W);
+
+impl<W: Write> Output for WriteOutput<W> {
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.0.write_all(&[byte])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Adapter that writes every byte as the UTF-8 encoding of the [`char`] with the same code point.
+///
+/// Bytes 0–127 are passed through unchanged, bytes 128–255 become two-byte sequences. This is the
+/// behavior of earlier versions, which printed `byte as char`.
+pub struct Utf8Output<O>(pub O);
+
+impl<O: Output> Output for Utf8Output<O> {
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        let mut buf = [0; 4];
+        for b in (byte as char).encode_utf8(&mut buf).bytes() {
+            self.0.write_byte(b)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_utf8_output() {
+        let mut out = Utf8Output(Vec::<u8>::new());
+        out.write_byte(b'a').unwrap();
+        out.write_byte(0xE9).unwrap();
+        assert_eq!(out.0, "aé".as_bytes());
+    }
+
+    #[test]
+    fn test_write_output() {
+        let mut out = WriteOutput(Cursor::new(Vec::new()));
+        out.write_byte(1).unwrap();
+        out.write_byte(255).unwrap();
+        assert_eq!(out.0.into_inner(), vec![1, 255]);
+    }
+
+    #[test]
+    fn test_write_output_error() {
+        let mut out = WriteOutput(&mut [][..]);
+        assert!(out.write_byte(1).is_err());
     }
 }
@@ -0,0 +1,223 @@
+use super::memoryband::MemoryBand;
+use super::observer::{Instruction, Observer};
+use super::sourcecode::{BfCommand, LineIndex, SourceCode, Span};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Execution statistics of a single loop, see [`Profiler::loops()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopProfile {
+    /// Span from the opening to the closing bracket
+    pub span: Span,
+    /// How often the execution reached the loop
+    pub entries: u64,
+    /// Iterations of all entries together
+    pub iterations: u64,
+    /// Instructions executed inside the loop including its condition checks and nested loops
+    pub instructions: u64,
+    /// Commands in the source code of the loop
+    pub source_commands: usize,
+    /// Instructions the optimizer turned the source commands into
+    pub optimized_instructions: usize,
+}
+
+impl LoopProfile {
+    /// Whether the optimizer rewrote the loop, i.e. merged some of its commands
+    pub fn rewritten(&self) -> bool {
+        self.optimized_instructions < self.source_commands
+    }
+}
+
+/// Counts the executions per source position and per loop.
+pub struct Profiler {
+    lines: LineIndex,
+    counts: HashMap<Span, u64>,
+    loops: HashMap<Span, LoopProfile>,
+    /// Total instructions executed when the currently running loops were entered
+    loop_stack: Vec<u64>,
+    instructions: u64,
+}
+
+impl Profiler {
+    /// Creates a profiler for `code`, which was parsed from `source`
+    pub fn new(source: &str, code: &SourceCode) -> Profiler {
+        let mut loops = HashMap::new();
+        collect_loops(source, code, &mut loops);
+        Profiler {
+            lines: LineIndex::new(source),
+            counts: HashMap::new(),
+            loops,
+            loop_stack: Vec::new(),
+            instructions: 0,
+        }
+    }
+
+    /// Total instructions executed
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Executions per source span, loop conditions are counted at their brackets
+    pub fn counts(&self) -> &HashMap<Span, u64> {
+        &self.counts
+    }
+
+    /// All loops of the program ordered by the instructions executed in them, hottest first
+    pub fn loops(&self) -> Vec<&LoopProfile> {
+        let mut loops: Vec<_> = self.loops.values().collect();
+        loops.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.span.cmp(&b.span)));
+        loops
+    }
+
+    /// Formats a report of the `top` hottest loops and source positions
+    pub fn report(&self, top: usize) -> String {
+        let mut report = String::new();
+        let _ = writeln!(report, "Profile of {} executed instructions", self.instructions);
+
+        let _ = writeln!(report, "\nHot loops:");
+        let _ = writeln!(report, "{:>10} {:>8} {:>12} {:>14}  {:<24} code",
+            "position", "entries", "iterations", "instructions", "rewritten");
+        for profile in self.loops().into_iter().take(top).filter(|profile| profile.entries > 0) {
+            let rewritten = if profile.rewritten() {
+                format!("{} commands to {}", profile.source_commands, profile.optimized_instructions)
+            } else {
+                String::from("no")
+            };
+            let _ = writeln!(report, "{:>10} {:>8} {:>12} {:>14}  {:<24} {}",
+                self.position(profile.span), profile.entries, profile.iterations, profile.instructions,
+                rewritten, self.excerpt(profile.span));
+        }
+
+        let _ = writeln!(report, "\nHot instructions:");
+        let _ = writeln!(report, "{:>10} {:>12}  code", "position", "executions");
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (span, count) in counts.into_iter().take(top) {
+            let _ = writeln!(report, "{:>10} {:>12}  {}", self.position(*span), count, self.excerpt(*span));
+        }
+        report
+    }
+
+    fn position(&self, span: Span) -> String {
+        let (line, column) = self.lines.line_col(span.start);
+        format!("{}:{}", line, column)
+    }
+
+    /// The source code of `span` on a single line, shortened to at most 40 characters
+    fn excerpt(&self, span: Span) -> String {
+        let text: String = self.lines.source()[span.start..span.end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if text.chars().count() > 40 {
+            format!("{}...", text.chars().take(37).collect::<String>())
+        } else {
+            text
+        }
+    }
+}
+
+impl Observer for Profiler {
+    fn instruction<M: MemoryBand>(&mut self, _: Instruction, span: Span, _: &M, _: u8) {
+        self.instructions += 1;
+        *self.counts.entry(span).or_insert(0) += 1;
+    }
+
+    fn loop_entered(&mut self, _: Span) {
+        self.loop_stack.push(self.instructions);
+    }
+
+    fn loop_exited(&mut self, span: Span, iterations: u64) {
+        let start = self.loop_stack.pop().unwrap_or(0);
+        if let Some(profile) = self.loops.get_mut(&span) {
+            profile.entries += 1;
+            profile.iterations += iterations;
+            profile.instructions += self.instructions - start;
+        }
+    }
+}
+
+/// Adds an empty profile for every loop in `code` to `loops`, returns the number of instructions
+/// in `code` including the brackets of its loops
+fn collect_loops(source: &str, code: &SourceCode, loops: &mut HashMap<Span, LoopProfile>) -> usize {
+    let mut instructions = 0;
+    for (command, span) in code.commands.iter().zip(code.spans.iter()) {
+        instructions += match command {
+            BfCommand::Loop(body) => {
+                let optimized_instructions = 2 + collect_loops(source, body, loops);
+                let source_commands = source[span.start..span.end]
+                    .chars()
+                    .filter(|c| "+-<>.,[]".contains(*c))
+                    .count()
+                    + count_debug(body);
+                loops.insert(*span, LoopProfile {
+                    span: *span,
+                    entries: 0,
+                    iterations: 0,
+                    instructions: 0,
+                    source_commands,
+                    optimized_instructions,
+                });
+                optimized_instructions
+            }
+            _ => 1,
+        };
+    }
+    instructions
+}
+
+/// Number of `#` debug commands in `code`, which only count as commands if they were enabled
+fn count_debug(code: &SourceCode) -> usize {
+    code.commands
+        .iter()
+        .map(|command| match command {
+            BfCommand::Debug => 1,
+            BfCommand::Loop(body) => count_debug(body),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::InfiniteMemoryBand;
+    use crate::observer::run_observed;
+
+    fn profile(source: &str) -> Profiler {
+        let code = source.parse::<SourceCode>().unwrap();
+        let mut profiler = Profiler::new(source, &code);
+        run_observed(&code, &mut InfiniteMemoryBand::new(), &[], &mut profiler);
+        profiler
+    }
+
+    #[test]
+    fn test_loops() {
+        let profiler = profile("+++[>++[>+<-]<-]");
+        let loops = profiler.loops();
+
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].span, Span { start: 3, end: 16 });
+        assert_eq!((loops[0].entries, loops[0].iterations), (1, 3));
+        assert_eq!(loops[0].instructions, profiler.instructions() - 1);
+        assert!(loops[0].rewritten());
+        assert_eq!((loops[0].source_commands, loops[0].optimized_instructions), (13, 12));
+
+        assert_eq!(loops[1].span, Span { start: 7, end: 13 });
+        assert_eq!((loops[1].entries, loops[1].iterations), (3, 6));
+        assert_eq!(loops[1].instructions, 3 * (1 + 2 * 5));
+        assert!(!loops[1].rewritten());
+    }
+
+    #[test]
+    fn test_counts_and_report() {
+        let profiler = profile("++[-]");
+        assert_eq!(profiler.counts()[&Span { start: 0, end: 2 }], 1);
+        assert_eq!(profiler.counts()[&Span { start: 3, end: 4 }], 2);
+        assert_eq!(profiler.counts()[&Span { start: 4, end: 5 }], 2);
+
+        let report = profiler.report(10);
+        assert!(report.starts_with("Profile of 6 executed instructions\n"));
+        assert!(report.contains("       1:3        1            2              5  no                       [-]\n"));
+    }
+}
@@ -0,0 +1,461 @@
+use super::debugger::write_cells;
+use super::input::Input;
+use super::memoryband::MemoryBand;
+use super::output::Output;
+use super::settings::Settings;
+use super::snapshot::MemorySnapshot;
+use super::sourcecode::{ParseOptions, SourceCode};
+use super::stats::Stats;
+use std::fs;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const HELP: &str = "\
+Lines are executed as brainfuck code on the same memory band, code with unclosed loops is
+continued on the next lines. Lines can also be one of these commands:
+  :mem [RADIUS]  show the cells around the head, 8 (at most 64) to each side
+  :head N        move the head to the cell at position N
+  :set N VALUE   write VALUE to the cell at position N
+  :reset         start over with a fresh memory band
+  :load FILE     restore the memory band from a snapshot file
+  :save FILE     save the memory band to a snapshot file
+  :stats         show statistics of the code executed so far
+  :history       list the code and the commands changing the band entered so far
+  :replay [FILE] run the code and commands listed by :history again on a fresh band, or
+                 those among the lines of FILE, e.g. the log of earlier sessions
+  :help          show this help
+  exit           leave the interpreter
+Positions are counted in cells right of the cell the head started on.";
+
+/// The interactive interpreter, executing lines of code on a memoryband that is kept between
+/// them. Lines starting with `:` are meta-commands to inspect and modify the memoryband, see
+/// `:help`.
+/// Code with unclosed loops is collected over several lines until all loops are closed.
+pub struct Repl<M> {
+    band: M,
+    /// Lines of code with unclosed loops
+    pending: String,
+    /// Executed code and meta-commands that changed the memoryband, to replay them
+    history: Vec<String>,
+    settings: Settings,
+    parse_options: ParseOptions,
+    stats: Stats,
+}
+
+impl<M: MemoryBand> Repl<M> {
+    pub fn new(band: M, settings: Settings, parse_options: ParseOptions) -> Repl<M> {
+        Repl {
+            band,
+            pending: String::new(),
+            history: Vec::new(),
+            settings,
+            parse_options,
+            stats: idle_stats(),
+        }
+    }
+
+    pub fn band(&self) -> &M {
+        &self.band
+    }
+
+    /// Whether the last lines of code contain unclosed loops, so the next line continues them
+    pub fn is_continued(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Discards the lines of code with unclosed loops, returns false if there were none
+    pub fn cancel(&mut self) -> bool {
+        let continued = self.is_continued();
+        self.pending.clear();
+        continued
+    }
+
+    /// The executed code and the meta-commands that changed the memoryband, in the order they
+    /// were entered
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// The prompt to show in front of the next line
+    pub fn prompt(&self) -> &'static str {
+        if self.is_continued() { "... " } else { "" }
+    }
+
+    /// Executes a line of code or a meta-command, reading the input of the code from `stdin` and
+    /// writing its output to `stdout` and all messages to `out`.
+    /// Code with unclosed loops is only executed once a later line closes them.
+    /// Returns false if the interpreter should exit.
+    pub fn execute<I, O, W>(&mut self, line: &str, stdin: &mut I, stdout: &mut O, out: &mut W) -> io::Result<bool>
+    where I: Input + ?Sized,
+          O: Output + ?Sized,
+          W: Write {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.starts_with("exit") {
+            writeln!(out, "Exiting...")?;
+            return Ok(false);
+        }
+        if let Some(command) = line.strip_prefix(':').filter(|_| !self.is_continued()) {
+            self.meta_command(command.trim(), stdin, stdout, out)?;
+            return Ok(true);
+        }
+
+        self.pending.push_str(line);
+        if has_unclosed_loop(&self.pending) {
+            self.pending.push('\n');
+            return Ok(true);
+        }
+        let line = std::mem::take(&mut self.pending);
+        match SourceCode::parse_with(&line, &self.parse_options) {
+            Ok(code) => {
+                self.history.push(line.clone());
+                // the statistics count the commands in their own source code
+                self.stats.set_source(&line);
+                write!(out, "[out]: ")?;
+                out.flush()?;
+                // waiting for the user to type the input does not count as running
+                let mut stdin = TimedInput { input: stdin, waited: Duration::ZERO };
+                self.stats.start();
+                let result = code.run_observed(&mut self.band, &mut stdin, stdout, &self.settings, &mut self.stats);
+                self.stats.stop();
+                self.stats.exclude(stdin.waited);
+                writeln!(out)?;
+                if let Err(e) = result {
+                    writeln!(out, "{}", e)?;
+                }
+            }
+            Err(e) => writeln!(out, "{}", e)?,
+        }
+        Ok(true)
+    }
+
+    fn meta_command<I, O, W>(&mut self, line: &str, stdin: &mut I, stdout: &mut O, out: &mut W) -> io::Result<()>
+    where I: Input + ?Sized,
+          O: Output + ?Sized,
+          W: Write {
+        let (command, rest) = match line.find(' ') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => (line, ""),
+        };
+        if changes_band(command) {
+            self.history.push(format!(":{}", line));
+        }
+        match command {
+            "mem" => match if rest.is_empty() { Ok(8) } else { rest.parse::<isize>() } {
+                Ok(radius) => write_cells(&self.band, radius, true, out)?,
+                Err(_) => writeln!(out, "Expected a radius.")?,
+            },
+            "head" => match rest.parse::<isize>() {
+                Ok(position) if self.offset_of(position).is_some() => {
+                    let moves = position - self.band.position();
+                    let moved = match self.settings.max_memory {
+                        Some(max_cells) => self.band.move_head_within(moves, max_cells),
+                        None => {
+                            self.band.move_head(moves);
+                            Ok(())
+                        }
+                    };
+                    if let Err(e) = moved {
+                        writeln!(out, "{}", e)?;
+                    }
+                }
+                Ok(_) => writeln!(out, "There is no cell at that position.")?,
+                Err(_) => writeln!(out, "Expected a position.")?,
+            },
+            "set" => {
+                let mut args = rest.split_whitespace();
+                let position = args.next().and_then(|s| s.parse::<isize>().ok());
+                let value = args.next().and_then(|s| s.parse::<u8>().ok());
+                match (position, value) {
+                    (Some(position), Some(value)) => match self.offset_of(position) {
+                        Some(offset) => {
+                            let written = match self.settings.max_memory {
+                                Some(max_cells) => self.band.poke_within(offset, value, max_cells),
+                                None => {
+                                    self.band.poke(offset, value);
+                                    Ok(())
+                                }
+                            };
+                            if let Err(e) = written {
+                                writeln!(out, "{}", e)?;
+                            }
+                        }
+                        None => writeln!(out, "There is no cell at that position.")?,
+                    },
+                    _ => writeln!(out, "Expected a position and a value from 0 to 255.")?,
+                }
+            }
+            "reset" => {
+                self.band = M::new();
+                self.stats = idle_stats();
+            }
+            "load" if !rest.is_empty() => {
+                let band = MemorySnapshot::load(rest)
+                    .map_err(|e| e.to_string())
+                    .and_then(|snapshot| M::restore(&snapshot));
+                match band {
+                    Ok(band) => self.band = band,
+                    Err(e) => writeln!(out, "Could not load '{}': {}", rest, e)?,
+                }
+            }
+            "save" if !rest.is_empty() => {
+                if let Err(e) = self.band.snapshot().save(rest) {
+                    writeln!(out, "Could not save '{}': {}", rest, e)?;
+                }
+            }
+            "load" | "save" => writeln!(out, "Expected a file name.")?,
+            "stats" => write!(out, "{}", self.stats.report())?,
+            "history" => {
+                for (i, line) in self.history.iter().enumerate() {
+                    writeln!(out, "{:>4}  {}", i + 1, line.replace('\n', "\n      "))?;
+                }
+            }
+            "replay" => {
+                let lines = if rest.is_empty() {
+                    Ok(std::mem::take(&mut self.history))
+                } else {
+                    fs::read_to_string(rest).map(|text| text.lines().map(String::from).collect())
+                };
+                match lines {
+                    Ok(lines) => {
+                        self.history.clear();
+                        self.band = M::new();
+                        self.stats = idle_stats();
+                        for line in lines.iter() {
+                            if !self.replays(line) {
+                                continue;
+                            }
+                            writeln!(out, "{}", line.replace('\n', "\n... "))?;
+                            self.execute(line, stdin, stdout, out)?;
+                        }
+                    }
+                    Err(e) => writeln!(out, "Could not read '{}': {}", rest, e)?,
+                }
+            }
+            "help" => writeln!(out, "{}", HELP)?,
+            _ => writeln!(out, "Unknown command ':{}', type ':help' for a list of commands.", command)?,
+        }
+        Ok(())
+    }
+
+    /// Whether `line` is replayed by `:replay`, which skips leaving the interpreter and the
+    /// commands that do not change the band
+    fn replays(&self, line: &str) -> bool {
+        if line.starts_with("exit") {
+            return false;
+        }
+        match line.strip_prefix(':') {
+            Some(command) if !self.is_continued() => changes_band(command.trim()),
+            _ => true,
+        }
+    }
+
+    /// Offset from the head of the cell at `position`, if the band has such a cell
+    fn offset_of(&self, position: isize) -> Option<isize> {
+        position
+            .checked_sub(self.band.position())
+            .filter(|offset| self.band.peek(*offset).is_some())
+    }
+}
+
+/// Whether the meta-command `command`, given without its `:`, changes the band and therefore
+/// belongs into the history replayed by `:replay`
+fn changes_band(command: &str) -> bool {
+    let name = command.split_whitespace().next().unwrap_or("");
+    matches!(name, "head" | "set" | "reset" | "load")
+}
+
+/// Input that measures how long reading from it took
+struct TimedInput<'a, I: ?Sized> {
+    input: &'a mut I,
+    waited: Duration,
+}
+
+impl<I: Input + ?Sized> Input for TimedInput<'_, I> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let start = Instant::now();
+        let byte = self.input.read_byte();
+        self.waited += start.elapsed();
+        byte
+    }
+}
+
+/// Statistics whose clock only runs while entered code executes
+fn idle_stats() -> Stats {
+    let mut stats = Stats::new("");
+    stats.stop();
+    stats
+}
+
+/// Whether `code` opens more loops than it closes, ignoring code that closes more loops than it
+/// has opened before, which cannot be fixed by continuing it
+fn has_unclosed_loop(code: &str) -> bool {
+    let mut depth = 0;
+    for c in code.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return false,
+            ']' => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::{FiniteMemoryBand, InfiniteMemoryBand};
+
+    fn repl<M: MemoryBand>(lines: &[&str]) -> (Repl<M>, String) {
+        let mut repl = Repl::new(M::new(), Settings::default(), ParseOptions::default());
+        let mut out = Vec::new();
+        for line in lines {
+            repl.execute(line, &mut std::iter::empty(), &mut out.clone(), &mut out).unwrap();
+        }
+        (repl, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_code_keeps_band() {
+        let (repl, out) = repl::<InfiniteMemoryBand>(&["+++>\n", "++<.\n"]);
+        assert_eq!(repl.band().read(), 3);
+        assert_eq!(repl.band().peek(1), Some(2));
+        assert_eq!(out, "[out]: \n[out]: \n");
+    }
+
+    #[test]
+    fn test_multiline() {
+        let mut repl = Repl::new(InfiniteMemoryBand::new(), Settings::default(), ParseOptions::default());
+        let mut out = Vec::new();
+        for line in ["+++[>++", ":mem", "[>+<-]", "<-]"] {
+            assert_eq!(repl.prompt(), if line == "+++[>++" { "" } else { "... " });
+            repl.execute(line, &mut std::iter::empty(), &mut Vec::<u8>::new(), &mut out).unwrap();
+        }
+        assert!(!repl.is_continued());
+        assert_eq!(repl.band().peek(2), Some(6));
+        assert_eq!(out, b"[out]: \n");
+    }
+
+    #[test]
+    fn test_unclosed_loop() {
+        assert!(has_unclosed_loop("+[>[-]"));
+        assert!(!has_unclosed_loop("+[>[-]]"));
+        assert!(!has_unclosed_loop("]["));
+
+        let (repl, out) = repl::<InfiniteMemoryBand>(&["+]["]);
+        assert!(!repl.is_continued());
+        assert!(out.starts_with("No matching bracket"));
+    }
+
+    #[test]
+    fn test_history_replay() {
+        let mut repl = Repl::new(InfiniteMemoryBand::new(), Settings::default(), ParseOptions::default());
+        let mut out = Vec::new();
+        let mut stdout = Vec::<u8>::new();
+        for line in ["+++[", ">++<-]", ":mem 0", ":set 1 1", ">.", ":replay", ":history"] {
+            repl.execute(line, &mut std::iter::empty(), &mut stdout, &mut out).unwrap();
+        }
+        assert_eq!(stdout, vec![1, 1]);
+        assert_eq!(repl.band().read(), 1);
+        assert_eq!(repl.history(), &["+++[\n>++<-]", ":set 1 1", ">."]);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with("   1  +++[\n      >++<-]\n   2  :set 1 1\n   3  >.\n"));
+    }
+
+    #[test]
+    fn test_head_set_mem() {
+        let (repl, out) = repl::<InfiniteMemoryBand>(&[":set -1 7", ":head -1", ":mem 1"]);
+        assert_eq!(repl.band().position(), -1);
+        assert_eq!(out, "cell      -2    -1     0\nvalue      0   [7]     0\n");
+    }
+
+    #[test]
+    fn test_head_outside_band() {
+        let (repl, out) = repl::<FiniteMemoryBand>(&[":head 20000", ":set x 1"]);
+        assert_eq!(repl.band().position(), 0);
+        assert_eq!(out, "There is no cell at that position.\nExpected a position and a value from 0 to 255.\n");
+    }
+
+    #[test]
+    fn test_mem_and_set_limits() {
+        let settings = Settings { max_memory: Some(10), ..Settings::default() };
+        let mut repl = Repl::new(InfiniteMemoryBand::new(), settings, ParseOptions::default());
+        let mut out = Vec::new();
+        for line in [":mem -9223372036854775808", ":set 1000000000 1", ":set -9223372036854775808 1", ":head 1000000000"] {
+            repl.execute(line, &mut std::iter::empty(), &mut out.clone(), &mut out).unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("cell     -64"));
+        assert_eq!(out.matches("memory limit").count(), 3, "{}", out);
+        assert_eq!(repl.band().snapshot().cells().len(), 1);
+    }
+
+    #[test]
+    fn test_reset_and_stats() {
+        let (repl, out) = repl::<InfiniteMemoryBand>(&["++[>+<-]", ":stats", ":reset"]);
+        assert_eq!(repl.band().peek(1), Some(0));
+        assert!(out.contains("  instructions:     12 (13 before optimization)\n"));
+    }
+
+    #[test]
+    fn test_stats_exclude_idle_time() {
+        let mut repl = Repl::new(InfiniteMemoryBand::new(), Settings::default(), ParseOptions::default());
+        std::thread::sleep(Duration::from_millis(50));
+        repl.execute("+", &mut std::iter::empty(), &mut Vec::<u8>::new(), &mut Vec::new()).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(repl.stats.wall_time() < Duration::from_millis(50));
+    }
+
+    /// A path in the temporary directory that no other test run uses
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("rsbrainfuck-{}-{}", std::process::id(), name));
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_replay_file() {
+        let path = temp_path("history");
+        fs::write(&path, "+++\n:mem\n[>++\n<-]\n:save x\n:set 5 1\n:replay x\nexit\n").unwrap();
+        let (repl, out) = repl::<InfiniteMemoryBand>(&[">+", &format!(":replay {}", path), ":replay missing-file"]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!((repl.band().position(), repl.band().peek(1), repl.band().peek(5)), (0, Some(6), Some(1)));
+        assert_eq!(repl.history(), ["+++", "[>++\n<-]", ":set 5 1"]);
+        assert!(out.contains("+++\n[out]: \n[>++\n<-]\n[out]: \n:set 5 1\n"), "{}", out);
+        assert!(out.ends_with("Could not read 'missing-file': No such file or directory (os error 2)\n"));
+    }
+
+    #[test]
+    fn test_stats_exclude_input_time() {
+        struct Slow;
+        impl Input for Slow {
+            fn read_byte(&mut self) -> io::Result<Option<u8>> {
+                std::thread::sleep(Duration::from_millis(50));
+                Ok(Some(1))
+            }
+        }
+        let mut repl = Repl::new(InfiniteMemoryBand::new(), Settings::default(), ParseOptions::default());
+        repl.execute(",", &mut Slow, &mut Vec::<u8>::new(), &mut Vec::new()).unwrap();
+        assert_eq!(repl.band().read(), 1);
+        assert!(repl.stats.wall_time() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = &temp_path("repl.snapshot");
+        let (repl, out) = repl::<InfiniteMemoryBand>(&[
+            "+++", &format!(":save {}", path), ":reset", &format!(":load {}", path), ":load",
+        ]);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(repl.band().read(), 3);
+        assert!(out.ends_with("Expected a file name.\n"));
+    }
+
+    #[test]
+    fn test_unknown_and_exit() {
+        let mut repl = Repl::new(InfiniteMemoryBand::new(), Settings::default(), ParseOptions::default());
+        let mut out = Vec::new();
+        assert!(repl.execute(":foo", &mut std::iter::empty(), &mut Vec::<u8>::new(), &mut out).unwrap());
+        assert!(!repl.execute("exit\n", &mut std::iter::empty(), &mut Vec::<u8>::new(), &mut out).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "Unknown command ':foo', type ':help' for a list of commands.\nExiting...\n");
+    }
+}
@@ -0,0 +1,148 @@
+use super::error::RuntimeError;
+use super::memoryband::MemoryBand;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What the `,` command does once the input is exhausted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EofPolicy {
+    /// Writes `0` to the current cell
+    #[default]
+    Zero,
+    /// Writes `-1`, i.e. `255`, to the current cell
+    MinusOne,
+    /// Leaves the current cell as it is
+    Unchanged,
+    /// Aborts the program with [`RuntimeError::UnexpectedEof`]
+    Error,
+}
+
+impl EofPolicy {
+    /// The names accepted by the `FromStr` implementation
+    pub const VARIANTS: [&'static str; 4] = ["zero", "minus-one", "unchanged", "error"];
+
+    /// Applies the policy to the current cell of `band` after an input request found no input
+    pub(crate) fn apply<M: MemoryBand>(self, band: &mut M) -> Result<(), RuntimeError> {
+        match self {
+            EofPolicy::Zero => band.write(0),
+            EofPolicy::MinusOne => band.write(u8::MAX),
+            EofPolicy::Unchanged => (),
+            EofPolicy::Error => return Err(RuntimeError::UnexpectedEof),
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EofPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(EofPolicy::Zero),
+            "minus-one" => Ok(EofPolicy::MinusOne),
+            "unchanged" => Ok(EofPolicy::Unchanged),
+            "error" => Ok(EofPolicy::Error),
+            _ => Err(format!(
+                "Unknown EOF policy '{}', expected one of {}.",
+                s,
+                EofPolicy::VARIANTS.join(", ")
+            )),
+        }
+    }
+}
+
+/// A flag that allows cancelling a running program from another thread.
+///
+/// Clones share the same flag, so keep one clone and put another into the [`Settings`] of the run.
+/// The interpreter checks the flag whenever a loop jumps back, and stops the program with
+/// [`RuntimeError::Cancelled`] once it is set.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Requests the cancellation of all runs using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Options that change how a [`super::sourcecode::SourceCode`] is executed.
+///
+/// Use `Settings::default()` for the standard behavior and override single fields with the
+/// struct update syntax, e.g. `Settings { eof: EofPolicy::Unchanged, ..Settings::default() }`.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// Behavior of `,` at the end of the input
+    pub eof: EofPolicy,
+    /// Maximum number of instructions to execute, every evaluation of a loop condition counts as
+    /// one instruction
+    pub max_steps: Option<u64>,
+    /// Maximum number of bytes to write to the output
+    pub max_output: Option<u64>,
+    /// Maximum number of cells an unbounded memoryband may grow to
+    pub max_memory: Option<usize>,
+    /// Maximum wall-clock time a run may take
+    pub timeout: Option<Duration>,
+    /// Token to cancel the run from another thread
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl Settings {
+    /// Whether a step or output budget, a memory limit, a timeout or a cancellation token limits
+    /// the execution
+    pub(crate) fn limits_execution(&self) -> bool {
+        self.max_steps.is_some()
+            || self.max_output.is_some()
+            || self.max_memory.is_some()
+            || self.timeout.is_some()
+            || self.cancellation.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::InfiniteMemoryBand;
+
+    #[test]
+    fn test_from_str() {
+        for name in EofPolicy::VARIANTS.iter() {
+            assert!(name.parse::<EofPolicy>().is_ok());
+        }
+        assert_eq!("minus-one".parse(), Ok(EofPolicy::MinusOne));
+        assert!("-1".parse::<EofPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut band = InfiniteMemoryBand::new();
+        band.write(5);
+
+        EofPolicy::Unchanged.apply(&mut band).unwrap();
+        assert_eq!(band.read(), 5);
+        EofPolicy::MinusOne.apply(&mut band).unwrap();
+        assert_eq!(band.read(), 255);
+        EofPolicy::Zero.apply(&mut band).unwrap();
+        assert_eq!(band.read(), 0);
+        assert!(matches!(EofPolicy::Error.apply(&mut band), Err(RuntimeError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+}
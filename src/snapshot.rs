@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// Magic bytes every snapshot file starts with, followed by a format version byte.
+const MAGIC: &[u8; 4] = b"RSBF";
+const VERSION: u8 = 1;
+
+/// A copy of the complete state of a [`super::memoryband::MemoryBand`]: the contents of its cells,
+/// the position of the reading head and the cell the head started on.
+///
+/// Snapshots are created with [`super::memoryband::MemoryBand::snapshot()`] and turned back into a
+/// memoryband with [`super::memoryband::MemoryBand::restore()`].
+/// They can be written to and read from files, so an expensive initialization can be computed once
+/// and reused for many runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemorySnapshot {
+    cells: Vec<u8>,
+    head: usize,
+    origin: usize,
+}
+
+impl MemorySnapshot {
+    /// Creates a new snapshot of `cells` with the reading head at index `head`, which is also
+    /// taken as the cell the head started on.
+    /// Fails if `head` does not point into `cells`.
+    pub fn new(cells: Vec<u8>, head: usize) -> Result<MemorySnapshot, String> {
+        MemorySnapshot::with_origin(cells, head, head)
+    }
+
+    /// Creates a new snapshot of `cells` with the reading head at index `head`, which started on
+    /// the cell at index `origin`.
+    /// Fails if `head` or `origin` do not point into `cells`.
+    pub fn with_origin(cells: Vec<u8>, head: usize, origin: usize) -> Result<MemorySnapshot, String> {
+        for (name, index) in [("head", head), ("origin", origin)].iter() {
+            if *index >= cells.len() {
+                return Err(format!(
+                    "The {} index {} lies outside of the {} stored cells.",
+                    name,
+                    index,
+                    cells.len()
+                ));
+            }
+        }
+        Ok(MemorySnapshot { cells, head, origin })
+    }
+
+    /// The stored cells, ordered from left to right
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Index of the cell below the reading head
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// Index of the cell the reading head started on
+    pub fn origin(&self) -> usize {
+        self.origin
+    }
+
+    /// Writes the snapshot in its binary format to `writer`: the magic bytes, the version, the
+    /// head index, the origin index and the number of cells as little-endian `u64`, then the cells.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&(self.head as u64).to_le_bytes())?;
+        writer.write_all(&(self.origin as u64).to_le_bytes())?;
+        writer.write_all(&(self.cells.len() as u64).to_le_bytes())?;
+        writer.write_all(&self.cells)
+    }
+
+    /// Reads a snapshot in its binary format from `reader`.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<MemorySnapshot> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("The file is no memory snapshot."));
+        }
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != VERSION {
+            return Err(invalid_data(&format!(
+                "Unsupported memory snapshot version {}.",
+                version[0]
+            )));
+        }
+        let head = read_u64(reader)? as usize;
+        let origin = read_u64(reader)? as usize;
+        let len = read_u64(reader)? as usize;
+        let mut cells = Vec::new();
+        reader.take(len as u64).read_to_end(&mut cells)?;
+        if cells.len() != len {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "The memory snapshot is truncated.",
+            ));
+        }
+        MemorySnapshot::with_origin(cells, head, origin).map_err(|e| invalid_data(&e))
+    }
+
+    /// Writes the snapshot to the file at `path`, replacing its contents.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a snapshot from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<MemorySnapshot> {
+        MemorySnapshot::read_from(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_head_outside() {
+        assert!(MemorySnapshot::new(vec![1, 2], 2).is_err());
+        assert!(MemorySnapshot::new(vec![], 0).is_err());
+        assert!(MemorySnapshot::with_origin(vec![1, 2], 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let snapshot = MemorySnapshot::with_origin(vec![0, 7, 255, 3], 2, 1).unwrap();
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+
+        let read = MemorySnapshot::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, snapshot);
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        let err = MemorySnapshot::read_from(&mut &b"NOPE\x01"[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let snapshot = MemorySnapshot::new(vec![1, 2, 3], 0).unwrap();
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        bytes.pop();
+        let err = MemorySnapshot::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
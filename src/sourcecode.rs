@@ -1,4 +1,8 @@
 use super::memoryband::*;
+use super::error::RuntimeError;
+use super::settings::Settings;
+use super::execution::Execution;
+use super::observer::{Instruction, Observer};
 use super::input::Input;
 use super::output::Output;
 //use char_stream::CharStream;
@@ -7,81 +11,303 @@ use BfCommand::*;
 
 /// The variants of this enum each represent a brainfuck command.
 #[derive(Debug, PartialEq)]
-enum BfCommand {
+pub(crate) enum BfCommand {
     Move(isize),
     Add(u8),
     Print,
     Read,
     Loop(SourceCode),
+    /// Prints the state of the memoryband to stderr, only produced with [`ParseOptions::debug_hash`]
+    Debug,
+}
+
+/// Options that change how source code is parsed, see [`SourceCode::parse_with()`].
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Treat `#` as a command that prints the head position and the cells around it to stderr,
+    /// instead of ignoring it like any other character
+    pub debug_hash: bool,
 }
 
 /// This struct is created mainly using its `FromStr` implementation, e.g. by invoking
 /// `from_str(s)` or `s.parse()`. Use `code.run()` to run the SourceCode.
 /// The Sourcecode can be executed using the [`SourceCode::run()`] or [`SourceCode::run_on_band()`] methods.
-/// One Specialty of these functions is that they accept every Iterator over [`char`] as their
-/// StdIn, use [`super::inputbuffer::InputBuffer`] for the standard StdIn-behavior.
-#[derive(Debug, PartialEq)]
-pub struct SourceCode(Vec<BfCommand>);
+/// One Specialty of these functions is that they accept every Iterator over [`u8`] as their
+/// StdIn, use [`super::input::InputBuffer`] for the standard StdIn-behavior.
+/// Input and output may also be trait objects, e.g. `&mut dyn Input`.
+#[derive(Debug)]
+pub struct SourceCode {
+    pub(crate) commands: Vec<BfCommand>,
+    /// The positions in the source code the commands were parsed from, in the same order
+    pub(crate) spans: Vec<Span>,
+}
+
+/// Two SourceCodes are equal if they consist of the same commands, no matter where in the source
+/// code these are located.
+impl PartialEq for SourceCode {
+    fn eq(&self, other: &SourceCode) -> bool {
+        self.commands == other.commands
+    }
+}
+
+/// Range of bytes `start..end` of the source code a command was parsed from.
+/// The span of a loop reaches from its opening to its closing bracket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Line and column of the start of the span in `source`, both counted from 1.
+    /// Build a [`LineIndex`] instead to look up many spans of the same source.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        LineIndex::new(source).line_col(self.start)
+    }
+
+    /// Index of the first byte at `line` and `column` of `source`, both counted from 1.
+    /// Returns `None` if the position lies outside of the source.
+    pub fn offset_of(source: &str, line: usize, column: usize) -> Option<usize> {
+        LineIndex::new(source).offset_of(line, column)
+    }
+}
+
+/// Translates between byte offsets into a source code and lines and columns, using a table of
+/// the line starts that is built once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    source: String,
+    /// Byte offsets of the first character of every line
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> LineIndex {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex {
+            source: source.to_owned(),
+            line_starts,
+        }
+    }
+
+    /// The indexed source code
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Line of the byte at `offset`, counted from 1
+    pub fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    /// Line and column of the byte at `offset`, both counted from 1
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        let line_start = self.line_starts[line - 1];
+        let column = self.source.get(line_start..offset).map_or(0, |s| s.chars().count());
+        (line, column + 1)
+    }
+
+    /// Index of the first byte at `line` and `column`, both counted from 1.
+    /// Returns `None` if the position lies outside of the source.
+    pub fn offset_of(&self, line: usize, column: usize) -> Option<usize> {
+        let line_start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let text = self.source[line_start..].split('\n').next()?;
+        let column = column.checked_sub(1)?;
+        match text.char_indices().nth(column) {
+            Some((i, _)) => Some(line_start + i),
+            None if column == text.chars().count() => Some(line_start + text.len()),
+            None => None,
+        }
+    }
+}
 
 impl SourceCode {
+    /// Parses `s` like the `FromStr` implementation does, but with the extensions enabled in
+    /// `options`
+    pub fn parse_with(s: &str, options: &ParseOptions) -> Result<SourceCode, String> {
+        parse(s, 0, options)
+    }
+
+    /// Creates a SourceCode without source positions
+    #[cfg(test)]
+    pub(crate) fn from_commands(commands: Vec<BfCommand>) -> SourceCode {
+        let spans = vec![Span { start: 0, end: 0 }; commands.len()];
+        SourceCode { commands, spans }
+    }
+
+    /// The positions in the source code the top-level commands were parsed from
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
     /// Runs the brainfuck source code on an empty memoryband.
     ///
-    /// Pass any Iterator over [`char`] as stdin to the method, 
-    /// use [`super::inputbuffer::InputBuffer`] for the standard StdIn-behavior.
-    pub fn run<I,O,M>(&self, stdin: &mut I, stdout: &mut O)
-    where I: Input,
-          O: Output,
+    /// Pass any Iterator over [`u8`] as stdin to the method, 
+    /// use [`super::input::InputBuffer`] for the standard StdIn-behavior.
+    pub fn run<I,O,M>(&self, stdin: &mut I, stdout: &mut O) -> Result<(), RuntimeError>
+    where I: Input + ?Sized,
+          O: Output + ?Sized,
           M: MemoryBand {
         let mut band = M::new();
-        self.run_on_band(&mut band, stdin, stdout);
+        self.run_on_band(&mut band, stdin, stdout)
     }
 
     /// Runs the brainfuck source code on the given `band` memoryband.
     ///
-    /// Pass any Iterator over [`char`] as stdin to the method, 
-    /// use [`super::inputbuffer::InputBuffer`] for the standard StdIn-behavior.
-    pub fn run_on_band<I,O,M>(&self, band: &mut M, stdin: &mut I, stdout: &mut O)
-    where I: Input,
-          O: Output,
+    /// Pass any Iterator over [`u8`] as stdin to the method, 
+    /// use [`super::input::InputBuffer`] for the standard StdIn-behavior.
+    pub fn run_on_band<I,O,M>(&self, band: &mut M, stdin: &mut I, stdout: &mut O) -> Result<(), RuntimeError>
+    where I: Input + ?Sized,
+          O: Output + ?Sized,
           M: MemoryBand {
-        self.run_loop_band(band, stdin, stdout);
-        println!("");
+        self.run_with(band, stdin, stdout, &Settings::default())
     }
 
-    /// Runs the brainfuck source code on the given `band` memoryband.
-    /// This method got outsourced form [run_on_band`] because taht method needs to print one
-    /// newline symbol at the end of the computaton.
-    /// Leaving the newline out caused problems where the stdout would be presented delayed to the
-    /// user.
-    fn run_loop_band<I,O,M>(&self, band: &mut M, stdin: &mut I, stdout: &mut O)
-    where I: Input,
-          O: Output,
+    /// Runs the brainfuck source code on the given `band` memoryband, configured by `settings`.
+    ///
+    /// `stdout` is flushed before every input request and once the program terminates.
+    /// If the run is stopped by an error, `band` keeps the state it had at that point.
+    pub fn run_with<I,O,M>(&self, band: &mut M, stdin: &mut I, stdout: &mut O, settings: &Settings) -> Result<(), RuntimeError>
+    where I: Input + ?Sized,
+          O: Output + ?Sized,
           M: MemoryBand {
-        for c in self.0.iter() {
-            match c {
-                Move(i) => band.move_head(*i),
-                Add(i) => band.add(*i),
-                Print => stdout.write_char(band.read() as char),
+        self.run_observed(band, stdin, stdout, settings, &mut ())
+    }
+
+    /// Runs the brainfuck source code like [`SourceCode::run_with`], reporting every executed
+    /// instruction and its effects to `observer`.
+    pub fn run_observed<I,O,M,B>(&self, band: &mut M, stdin: &mut I, stdout: &mut O, settings: &Settings, observer: &mut B) -> Result<(), RuntimeError>
+    where I: Input + ?Sized,
+          O: Output + ?Sized,
+          M: MemoryBand,
+          B: Observer {
+        // without limits the bookkeeping compiles away
+        let result = if settings.limits_execution() {
+            let mut execution = Execution::<true>::new(settings.clone());
+            self.run_loop_band(band, stdin, stdout, &mut execution, observer)
+        } else {
+            let mut execution = Execution::<false>::new(settings.clone());
+            self.run_loop_band(band, stdin, stdout, &mut execution, observer)
+        };
+        stdout.flush()?;
+        result
+    }
+
+    /// Runs the brainfuck source code on the given `band` memoryband.
+    /// This method got outsourced form [`SourceCode::run_observed`] because that method needs to
+    /// flush the output once at the end of the computaton.
+    fn run_loop_band<I,O,M,B,const LIMITED: bool>(&self, band: &mut M, stdin: &mut I, stdout: &mut O, execution: &mut Execution<LIMITED>, observer: &mut B) -> Result<(), RuntimeError>
+    where I: Input + ?Sized,
+          O: Output + ?Sized,
+          M: MemoryBand,
+          B: Observer {
+        for (index, c) in self.commands.iter().enumerate() {
+            execution.check_step()?;
+            let before = if B::ACTIVE { band.read() } else { 0 };
+            let instruction = match c {
+                Move(i) => {
+                    execution.move_head(band, *i)?;
+                    if B::ACTIVE {
+                        let to = band.position();
+                        observer.head_moved(to - *i, to);
+                    }
+                    Instruction::Move(*i)
+                },
+                Add(i) => {
+                    band.add(*i);
+                    if B::ACTIVE {
+                        observer.cell_written(band.position(), before, band.read());
+                    }
+                    Instruction::Add(*i)
+                },
+                Print => {
+                    execution.output()?;
+                    let byte = band.read();
+                    stdout.write_byte(byte)?;
+                    if B::ACTIVE {
+                        observer.cell_read(band.position(), byte);
+                        observer.output(byte);
+                    }
+                    Instruction::Print
+                },
                 Read => {
-                        match stdin.read_char() {
-                        Some(c) => band.write(c as u8),
-                        None => band.write(0),
+                        stdout.flush()?;
+                        let byte = stdin.read_byte()?;
+                        if B::ACTIVE {
+                            observer.input(byte);
+                        }
+                        match byte {
+                        Some(c) => band.write(c),
+                        None => execution.settings.eof.apply(band)?,
+                    }
+                    if B::ACTIVE {
+                        observer.cell_written(band.position(), before, band.read());
                     }
+                    Instruction::Read
                 },
                 Loop(code) => {
+                    execution.count_step();
+                    let span = self.spans[index];
+                    let open = Span { start: span.start, end: span.start + 1 };
+                    let close = Span { start: span.end - 1, end: span.end };
+                    if B::ACTIVE {
+                        observer.loop_entered(span);
+                        observer.cell_read(band.position(), before);
+                        observer.instruction(Instruction::LoopStart, open, band, before);
+                    }
+                    let mut iterations = 0;
                     while band.read() != 0 {
-                        code.run_loop_band(band, stdin, stdout);
+                        code.run_loop_band(band, stdin, stdout, execution, observer)?;
+                        execution.back_edge()?;
+                        execution.step()?;
+                        iterations += 1;
+                        if B::ACTIVE {
+                            let value = band.read();
+                            observer.cell_read(band.position(), value);
+                            observer.instruction(Instruction::LoopEnd, close, band, value);
+                        }
+                    }
+                    if B::ACTIVE {
+                        observer.loop_exited(span, iterations);
                     }
+                    continue;
+                }
+                Debug => {
+                    stdout.flush()?;
+                    execution.dump(band);
+                    Instruction::Debug
                 }
+            };
+            execution.count_step();
+            if B::ACTIVE {
+                observer.instruction(instruction, self.spans[index], band, before);
             }
         }
+        Ok(())
+    }
+}
+
+/// Splits a source file following the convention of appending the program's input after a `!`.
+///
+/// Returns the code in front of the first `!` and everything behind it, or the whole string and
+/// `None` if it contains no `!`.
+pub fn split_embedded_input(s: &str) -> (&str, Option<&str>) {
+    match s.find('!') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
     }
 }
 
 /// s: string slice to find the brackets in
 /// start_index: index of the opening bracket
 fn find_matching_closing_bracket(s: &str, start_index: usize) -> Result<usize, String> {
-    let mut iter = s.char_indices().skip(start_index);
+    let mut iter = s.get(start_index..).unwrap_or("").char_indices();
     match iter.next() {
         Some((_, '[')) => (),
         _ => return Err(format!("No opening bracket at index {}.", start_index)),
@@ -94,7 +320,7 @@ fn find_matching_closing_bracket(s: &str, start_index: usize) -> Result<usize, S
             _ => continue,
         }
         if count == 0 {
-            return Ok(i);
+            return Ok(start_index + i);
         }
     }
     Err(format!(
@@ -105,80 +331,89 @@ fn find_matching_closing_bracket(s: &str, start_index: usize) -> Result<usize, S
 
 /// s: string slice to extract the loop from
 /// start_index: index of the opening bracket
-/// output: OK: the extracted loop Sourcecode and a length of the underlying string segment
-fn extract_loop_code(s: &str, start_index: usize) -> Result<(SourceCode, usize), String> {
-    let mut iter = s.chars().skip(start_index);
-    match iter.next() {
-        Some('[') => (),
-        _ => return Err(format!("No opening bracket at index {}.", start_index)),
-    };
+/// offset: index of `s` in the complete source code
+/// options: the extensions to parse
+/// output: OK: the extracted loop Sourcecode and the index of the closing bracket
+fn extract_loop_code(s: &str, start_index: usize, offset: usize, options: &ParseOptions) -> Result<(SourceCode, usize), String> {
     let close_index = find_matching_closing_bracket(s, start_index)?;
-    Ok((SourceCode::from_str(&s[start_index + 1..close_index])?, close_index - start_index))
+    Ok((parse(&s[start_index + 1..close_index], offset + start_index + 1, options)?, close_index))
 }
 
-impl FromStr for SourceCode {
-    type Err = String;
+/// Parses `s`, which starts at index `offset` of the complete source code.
+/// Runs of `<>` and `+-` are folded into a single command whose span covers the whole run.
+fn parse(s: &str, offset: usize, options: &ParseOptions) -> Result<SourceCode, String> {
+    let mut commands = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut commands = Vec::new();
-
-        let mut iter = s.char_indices();
+    let mut iter = s.char_indices();
 
-        // remove Move(0), Add(0) ?
-        while let Some((i, c)) = iter.next() {
-            match c {
-                '>' => match commands.last() {
-                    Some(&Move(d)) => {
-                        commands.pop();
-                        commands.push(Move(d + 1));
-                    }
-                    _ => commands.push(Move(1)),
-                },
-                '<' => match commands.last() {
-                    Some(&Move(d)) => {
-                        commands.pop();
-                        commands.push(Move(d - 1));
-                    }
-                    _ => commands.push(Move(-1)),
-                },
-
-                '+' => match commands.last() {
-                    Some(&Add(d)) => {
-                        commands.pop();
-                        commands.push(Add(d + 1));
-                    }
-                    _ => commands.push(Add(1)),
-                },
-                '-' => match commands.last() {
-                    Some(&Add(d)) => {
-                        commands.pop();
-                        commands.push(Add(d.overflowing_sub(1).0));
-                    }
-                    _ => commands.push(Add(u8::MAX)), // u8::MAX <=> -1
-                },
-
-                '.' => commands.push(Print),
-                ',' => commands.push(Read),
+    // remove Move(0), Add(0) ?
+    while let Some((i, c)) = iter.next() {
+        let folded = match (c, commands.last_mut()) {
+            ('>', Some(Move(d))) => {
+                *d += 1;
+                true
+            }
+            ('<', Some(Move(d))) => {
+                *d -= 1;
+                true
+            }
+            ('+', Some(Add(d))) => {
+                *d = d.overflowing_add(1).0;
+                true
+            }
+            ('-', Some(Add(d))) => {
+                *d = d.overflowing_sub(1).0;
+                true
+            }
+            _ => false,
+        };
+        if folded {
+            // the command was merged into the previous one, which now reaches up to here
+            spans.last_mut().unwrap().end = offset + i + 1;
+            continue;
+        }
 
-                '[' => {
-                    let (loop_code, len) = extract_loop_code(s, i)?;
-                    for _ in 0..len {
-                        iter.next();
+        match c {
+            '>' => commands.push(Move(1)),
+            '<' => commands.push(Move(-1)),
+            '+' => commands.push(Add(1)),
+            '-' => commands.push(Add(u8::MAX)), // u8::MAX <=> -1
+            '.' => commands.push(Print),
+            ',' => commands.push(Read),
+            '#' if options.debug_hash => commands.push(Debug),
+
+            '[' => {
+                let (loop_code, close_index) = extract_loop_code(s, i, offset, options)?;
+                for (j, _) in iter.by_ref() {
+                    if j == close_index {
+                        break;
                     }
-                    commands.push(Loop(loop_code));
-                }
-                ']' => {
-                    return Err(format!(
-                        "No matching bracket was found for ']' at position {}.",
-                        i
-                    ))
                 }
-
-                _ => continue,
+                commands.push(Loop(loop_code));
+                spans.push(Span { start: offset + i, end: offset + close_index + 1 });
+                continue;
+            }
+            ']' => {
+                return Err(format!(
+                    "No matching bracket was found for ']' at position {}.",
+                    offset + i
+                ))
             }
+
+            _ => continue,
         }
+        spans.push(Span { start: offset + i, end: offset + i + 1 });
+    }
+
+    Ok(SourceCode { commands, spans })
+}
 
-        Ok(SourceCode(commands))
+impl FromStr for SourceCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s, 0, &ParseOptions::default())
     }
 }
 
@@ -191,7 +426,7 @@ mod test {
         let source = "+-->><<<+++---";
         let code = source.parse::<SourceCode>();
 
-        let expected = Ok(SourceCode(vec![Add(NEG1), Move(-1), Add(0)]));
+        let expected = Ok(SourceCode::from_commands(vec![Add(NEG1), Move(-1), Add(0)]));
 
         assert_eq!(code, expected);
     }
@@ -219,13 +454,13 @@ mod test {
     fn test_ectract_loop_code() {
         let code = ".[.[+ ] ].".parse::<SourceCode>();
 
-        let expected: Result<SourceCode, String> = Ok(SourceCode(
+        let expected: Result<SourceCode, String> = Ok(SourceCode::from_commands(
                 vec![
                 Print,
-                Loop(SourceCode(
+                Loop(SourceCode::from_commands(
                         vec![
                         Print,
-                        Loop(SourceCode(
+                        Loop(SourceCode::from_commands(
                                 vec![
                                 Add(1)]))
                         ])),
@@ -258,9 +493,9 @@ mod test {
     fn test_from_str_loop() {
         let code = "+[--]+".parse::<SourceCode>();
 
-        let expected = Ok(SourceCode(vec![
+        let expected = Ok(SourceCode::from_commands(vec![
             Add(1),
-            Loop(SourceCode(vec![Add(NEG1-1)])),
+            Loop(SourceCode::from_commands(vec![Add(NEG1-1)])),
             Add(1),
         ]));
 
@@ -271,21 +506,21 @@ mod test {
     fn test_from_str_many_loops() {
         let code1 = "+[--]++[--]+".parse::<SourceCode>();
 
-        let expected1 = Ok(SourceCode(vec![
+        let expected1 = Ok(SourceCode::from_commands(vec![
             Add(1),
-            Loop(SourceCode(vec![Add(NEG1-1)])),
+            Loop(SourceCode::from_commands(vec![Add(NEG1-1)])),
             Add(2),
-            Loop(SourceCode(vec![Add(NEG1-1)])),
+            Loop(SourceCode::from_commands(vec![Add(NEG1-1)])),
             Add(1),
         ]));
 
         let code2 = "+[-[.-]+].".parse::<SourceCode>();
 
-        let expected2 = Ok(SourceCode(vec![
+        let expected2 = Ok(SourceCode::from_commands(vec![
                                       Add(1),
-                                      Loop(SourceCode(vec![
+                                      Loop(SourceCode::from_commands(vec![
                                                       Add(NEG1),
-                                                      Loop(SourceCode(vec![
+                                                      Loop(SourceCode::from_commands(vec![
                                                                       Print,
                                                                       Add(NEG1),
                                                       ])),
@@ -302,8 +537,234 @@ mod test {
     fn test_from_str_empty_loop() {
         let code = "[]".parse::<SourceCode>();
 
-        let expected = Ok(SourceCode(vec![Loop(SourceCode(vec![]))]));
+        let expected = Ok(SourceCode::from_commands(vec![Loop(SourceCode::from_commands(vec![]))]));
 
         assert_eq!(code, expected);
     }
+
+    #[test]
+    fn test_run_binary_roundtrip() {
+        let code = ",[.,]".parse::<SourceCode>().unwrap();
+        let data: Vec<u8> = (1..=255).collect();
+        let mut out: Vec<u8> = Vec::new();
+
+        code.run::<_, _, InfiniteMemoryBand>(&mut data.clone().into_iter(), &mut out).unwrap();
+
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn test_run_io_error() {
+        use crate::output::WriteOutput;
+        let code = "+.".parse::<SourceCode>().unwrap();
+        let mut out = WriteOutput(&mut [][..]);
+
+        let result = code.run::<_, _, InfiniteMemoryBand>(&mut std::iter::empty(), &mut out);
+
+        assert!(matches!(result, Err(RuntimeError::Io(_))));
+    }
+
+    #[test]
+    fn test_run_flushes() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+        impl Output for Recorder {
+            fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+                self.0.push(format!("write {}", byte));
+                Ok(())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.0.push(String::from("flush"));
+                Ok(())
+            }
+        }
+        let code = "+.,.".parse::<SourceCode>().unwrap();
+        let mut out = Recorder::default();
+
+        code.run::<_, _, InfiniteMemoryBand>(&mut vec![7].into_iter(), &mut out).unwrap();
+
+        assert_eq!(out.0, vec!["write 1", "flush", "write 7", "flush"]);
+    }
+
+    #[test]
+    fn test_run_eof_policy() {
+        use crate::settings::EofPolicy;
+        let code = "+,".parse::<SourceCode>().unwrap();
+        let run = |eof| {
+            let mut band = InfiniteMemoryBand::new();
+            let settings = Settings { eof, ..Settings::default() };
+            code.run_with(&mut band, &mut std::iter::empty(), &mut Vec::<u8>::new(), &settings)
+                .map(|_| band.read())
+        };
+
+        assert_eq!(run(EofPolicy::Zero).unwrap(), 0);
+        assert_eq!(run(EofPolicy::MinusOne).unwrap(), 255);
+        assert_eq!(run(EofPolicy::Unchanged).unwrap(), 1);
+        assert!(matches!(run(EofPolicy::Error), Err(RuntimeError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn test_split_embedded_input() {
+        assert_eq!(split_embedded_input(",[.,]!input!"), (",[.,]", Some("input!")));
+        assert_eq!(split_embedded_input(",[.,]!"), (",[.,]", Some("")));
+        assert_eq!(split_embedded_input(",[.,]"), (",[.,]", None));
+    }
+
+    #[test]
+    fn test_run_max_steps() {
+        let code = "+[]".parse::<SourceCode>().unwrap();
+        let settings = Settings { max_steps: Some(10), ..Settings::default() };
+        let mut band = InfiniteMemoryBand::new();
+
+        let result = code.run_with(&mut band, &mut std::iter::empty(), &mut Vec::<u8>::new(), &settings);
+
+        assert!(matches!(result, Err(RuntimeError::BudgetExceeded { steps: 10, output_bytes: 0 })));
+    }
+
+    #[test]
+    fn test_run_max_output() {
+        let code = "+[.]".parse::<SourceCode>().unwrap();
+        let settings = Settings { max_output: Some(3), ..Settings::default() };
+        let mut band = InfiniteMemoryBand::new();
+        let mut out: Vec<u8> = Vec::new();
+
+        let result = code.run_with(&mut band, &mut std::iter::empty(), &mut out, &settings);
+
+        assert!(matches!(result, Err(RuntimeError::BudgetExceeded { output_bytes: 3, .. })));
+        assert_eq!(out, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn test_run_within_budget() {
+        let code = "++[-]".parse::<SourceCode>().unwrap();
+        // Add, Loop, then two iterations of Add and the loop condition
+        let settings = Settings { max_steps: Some(6), ..Settings::default() };
+        let mut band = InfiniteMemoryBand::new();
+
+        let result = code.run_with(&mut band, &mut std::iter::empty(), &mut Vec::<u8>::new(), &settings);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_run_cancelled() {
+        use crate::settings::CancellationToken;
+        let code = "+[>+<]".parse::<SourceCode>().unwrap();
+        let token = CancellationToken::new();
+        let settings = Settings { cancellation: Some(token.clone()), ..Settings::default() };
+        let mut band = InfiniteMemoryBand::new();
+        token.cancel();
+
+        let result = code.run_with(&mut band, &mut std::iter::empty(), &mut Vec::<u8>::new(), &settings);
+
+        assert!(matches!(result, Err(RuntimeError::Cancelled)));
+        band.move_head(1);
+        assert_eq!(band.read(), 1);
+    }
+
+    #[test]
+    fn test_run_timed_out() {
+        let code = "+[]".parse::<SourceCode>().unwrap();
+        let settings = Settings {
+            timeout: Some(std::time::Duration::from_millis(10)),
+            ..Settings::default()
+        };
+        let mut band = InfiniteMemoryBand::new();
+
+        let result = code.run_with(&mut band, &mut std::iter::empty(), &mut Vec::<u8>::new(), &settings);
+
+        assert!(matches!(result, Err(RuntimeError::TimedOut)));
+    }
+
+    #[test]
+    fn test_run_memory_limit() {
+        let code = "+[>+]".parse::<SourceCode>().unwrap();
+        let settings = Settings { max_memory: Some(100), ..Settings::default() };
+        let mut band = InfiniteMemoryBand::new();
+
+        let result = code.run_with(&mut band, &mut std::iter::empty(), &mut Vec::<u8>::new(), &settings);
+
+        assert!(matches!(result, Err(RuntimeError::MemoryLimit { max_cells: 100 })));
+        assert_eq!(band.snapshot().cells().len(), 100);
+    }
+
+    #[test]
+    fn test_spans() {
+        let source = "+ ++ é>.\n[-]";
+        let code = source.parse::<SourceCode>().unwrap();
+
+        assert_eq!(code.spans(), &[
+            Span { start: 0, end: 4 },
+            Span { start: 7, end: 8 },
+            Span { start: 8, end: 9 },
+            Span { start: 10, end: 13 },
+        ]);
+        match &code.commands[3] {
+            Loop(body) => assert_eq!(body.spans(), &[Span { start: 11, end: 12 }]),
+            _ => panic!("expected a loop"),
+        }
+        assert_eq!(code.spans()[2].line_col(source), (1, 8));
+        assert_eq!(code.spans()[3].line_col(source), (2, 1));
+    }
+
+    #[test]
+    fn test_offset_of() {
+        let source = "+é+\n[-]";
+        assert_eq!(Span::offset_of(source, 1, 3), Some(3));
+        assert_eq!(Span::offset_of(source, 2, 2), Some(6));
+        assert_eq!(Span::offset_of(source, 3, 1), None);
+        assert_eq!(Span::offset_of(source, 1, 9), None);
+    }
+
+    #[test]
+    fn test_line_index() {
+        let lines = LineIndex::new("+é+\n\n[-]");
+        assert_eq!(lines.line(0), 1);
+        assert_eq!(lines.line(4), 1);
+        assert_eq!(lines.line(5), 2);
+        assert_eq!(lines.line_col(3), (1, 3));
+        assert_eq!(lines.line_col(7), (3, 2));
+        assert_eq!(lines.offset_of(3, 1), Some(6));
+        assert_eq!(lines.offset_of(0, 1), None);
+    }
+
+    #[test]
+    fn test_from_str_unicode_in_loop() {
+        let code = "[ä+]é.".parse::<SourceCode>();
+
+        let expected = Ok(SourceCode::from_commands(vec![
+            Loop(SourceCode::from_commands(vec![Add(1)])),
+            Print,
+        ]));
+
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn test_parse_debug_hash() {
+        let options = ParseOptions { debug_hash: true };
+        let code = SourceCode::parse_with("+#[#]", &options);
+
+        let expected = Ok(SourceCode::from_commands(vec![
+            Add(1),
+            Debug,
+            Loop(SourceCode::from_commands(vec![Debug])),
+        ]));
+
+        assert_eq!(code, expected);
+        assert_eq!("+#[#]".parse::<SourceCode>(), Ok(SourceCode::from_commands(vec![
+            Add(1),
+            Loop(SourceCode::from_commands(vec![])),
+        ])));
+    }
+
+    #[test]
+    fn test_run_debug_hash_keeps_stdout() {
+        let code = SourceCode::parse_with("+#.", &ParseOptions { debug_hash: true }).unwrap();
+        let mut out: Vec<u8> = Vec::new();
+
+        code.run::<_, _, InfiniteMemoryBand>(&mut std::iter::empty(), &mut out).unwrap();
+
+        assert_eq!(out, vec![1]);
+    }
 }
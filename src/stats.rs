@@ -0,0 +1,196 @@
+use super::memoryband::MemoryBand;
+use super::observer::{Instruction, Observer};
+use super::sourcecode::Span;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Collects summary statistics of a run.
+pub struct Stats {
+    source: String,
+    /// Wall time measured before the clock was last started
+    elapsed: Duration,
+    /// When the clock was started, if it is running
+    started: Option<Instant>,
+    /// Instructions executed
+    pub instructions: u64,
+    /// Source commands the executed instructions stand for, before runs were merged
+    pub source_commands: u64,
+    /// Iterations of all loops together
+    pub loop_iterations: u64,
+    /// Leftmost head position reached
+    pub min_head: isize,
+    /// Rightmost head position reached
+    pub max_head: isize,
+    /// Whether the head positions were seeded from the first event, before which they are 0
+    head_seen: bool,
+    /// Positions of the cells that were read or written
+    pub cells: HashSet<isize>,
+    /// Bytes consumed from the input
+    pub input_bytes: u64,
+    /// Bytes written to the output
+    pub output_bytes: u64,
+}
+
+impl Stats {
+    /// Creates empty statistics for a program parsed from `source`, the wall time is measured
+    /// from now on
+    pub fn new(source: &str) -> Stats {
+        Stats {
+            source: source.to_owned(),
+            elapsed: Duration::ZERO,
+            started: Some(Instant::now()),
+            instructions: 0,
+            source_commands: 0,
+            loop_iterations: 0,
+            min_head: 0,
+            max_head: 0,
+            head_seen: false,
+            cells: HashSet::new(),
+            input_bytes: 0,
+            output_bytes: 0,
+        }
+    }
+
+    /// Continues the statistics with a program parsed from `source`
+    pub fn set_source(&mut self, source: &str) {
+        self.source = source.to_owned();
+    }
+
+    /// Stops measuring the wall time, e.g. while no program runs
+    pub fn stop(&mut self) {
+        if let Some(started) = self.started.take() {
+            self.elapsed += started.elapsed();
+        }
+    }
+
+    /// Continues measuring the wall time after [`Stats::stop()`]
+    pub fn start(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    /// Leaves `time` out of the wall time, e.g. the time spent waiting for input
+    pub fn exclude(&mut self, time: Duration) {
+        self.elapsed = self.elapsed.saturating_sub(time);
+    }
+
+    /// Time passed since the statistics were created, without the time the clock was stopped
+    pub fn wall_time(&self) -> Duration {
+        self.elapsed + self.started.map_or(Duration::ZERO, |started| started.elapsed())
+    }
+
+    /// Widens the range of head positions to `position`. The band may not start at position 0,
+    /// e.g. after loading a snapshot, so the first position seen starts the range.
+    fn head_at(&mut self, position: isize) {
+        if !self.head_seen {
+            self.head_seen = true;
+            self.min_head = position;
+            self.max_head = position;
+        }
+        self.min_head = self.min_head.min(position);
+        self.max_head = self.max_head.max(position);
+    }
+
+    /// Formats the statistics as a table
+    pub fn report(&self) -> String {
+        let mut report = String::from("Statistics:\n");
+        let _ = writeln!(report, "  instructions:     {} ({} before optimization)", self.instructions, self.source_commands);
+        let _ = writeln!(report, "  loop iterations:  {}", self.loop_iterations);
+        let _ = writeln!(report, "  head positions:   {} to {}", self.min_head, self.max_head);
+        let _ = writeln!(report, "  cells touched:    {}", self.cells.len());
+        let _ = writeln!(report, "  input bytes:      {}", self.input_bytes);
+        let _ = writeln!(report, "  output bytes:     {}", self.output_bytes);
+        let _ = writeln!(report, "  wall time:        {:?}", self.wall_time());
+        report
+    }
+}
+
+impl Observer for Stats {
+    fn instruction<M: MemoryBand>(&mut self, instruction: Instruction, span: Span, _: &M, _: u8) {
+        self.instructions += 1;
+        self.source_commands += match instruction {
+            Instruction::Move(_) | Instruction::Add(_) => self.source[span.start..span.end]
+                .chars()
+                .filter(|c| "+-<>".contains(*c))
+                .count() as u64,
+            _ => 1,
+        };
+    }
+
+    fn loop_exited(&mut self, _: Span, iterations: u64) {
+        self.loop_iterations += iterations;
+    }
+
+    fn cell_read(&mut self, position: isize, _: u8) {
+        self.cells.insert(position);
+        self.head_at(position);
+    }
+
+    fn cell_written(&mut self, position: isize, _: u8, _: u8) {
+        self.cells.insert(position);
+        self.head_at(position);
+    }
+
+    fn head_moved(&mut self, from: isize, to: isize) {
+        self.head_at(from);
+        self.head_at(to);
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        if byte.is_some() {
+            self.input_bytes += 1;
+        }
+    }
+
+    fn output(&mut self, _: u8) {
+        self.output_bytes += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::InfiniteMemoryBand;
+    use crate::observer::run_observed;
+    use crate::sourcecode::SourceCode;
+
+    #[test]
+    fn test_stats() {
+        let source = ",,+ +[-<<+>>]<<.";
+        let code = source.parse::<SourceCode>().unwrap();
+        let mut stats = Stats::new(source);
+        run_observed(&code, &mut InfiniteMemoryBand::new(), b"a", &mut stats);
+
+        // , , ++ [ then 2 iterations of 4 instructions and a condition check, << .
+        assert_eq!(stats.instructions, 4 + 2 * 5 + 2);
+        assert_eq!(stats.source_commands, 4 + 1 + 2 * (6 + 1) + 2 + 1);
+        assert_eq!(stats.loop_iterations, 2);
+        assert_eq!((stats.min_head, stats.max_head), (-2, 0));
+        assert_eq!(stats.cells.len(), 2);
+        assert_eq!((stats.input_bytes, stats.output_bytes), (1, 1));
+        assert!(stats.report().contains("  instructions:     16 (22 before optimization)\n"));
+    }
+
+    #[test]
+    fn test_head_positions_of_moved_band() {
+        let code = "<".parse::<SourceCode>().unwrap();
+        let mut stats = Stats::new("<");
+        let mut band = InfiniteMemoryBand::new();
+        band.move_head(5);
+        run_observed(&code, &mut band, &[], &mut stats);
+
+        assert_eq!((stats.min_head, stats.max_head), (4, 5));
+    }
+
+    #[test]
+    fn test_stopped_clock() {
+        let mut stats = Stats::new("");
+        stats.stop();
+        let wall_time = stats.wall_time();
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(stats.wall_time(), wall_time);
+        stats.start();
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(stats.wall_time() >= wall_time + Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,134 @@
+use super::memoryband::MemoryBand;
+use super::observer::{Instruction, Observer};
+use super::sourcecode::{LineIndex, Span};
+use std::io::{self, Write};
+
+/// Records executed instructions as JSON Lines.
+///
+/// Every record holds the step number, the source position as byte range and as line and column,
+/// the instruction, the head position and the value of the current cell before and after the
+/// instruction, e.g.
+/// `{"step":3,"start":2,"end":4,"line":1,"column":3,"op":"Add(2)","head":0,"before":1,"after":3}`.
+pub struct Tracer<W> {
+    out: W,
+    lines: LineIndex,
+    every: u64,
+    kinds: Option<Vec<String>>,
+    step: u64,
+    /// Instructions of the recorded kinds executed so far, which the sampling counts
+    matched: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Creates a tracer that writes the records for a program parsed from `source` to `out`
+    pub fn new(out: W, source: &str) -> Tracer<W> {
+        Tracer {
+            out,
+            lines: LineIndex::new(source),
+            every: 1,
+            kinds: None,
+            step: 0,
+            matched: 0,
+            error: None,
+        }
+    }
+
+    /// Only records every `n`th executed instruction, counting only instructions of the kinds
+    /// given to [`Tracer::only_kinds()`]
+    pub fn sample_every(mut self, n: u64) -> Tracer<W> {
+        self.every = n.max(1);
+        self
+    }
+
+    /// Only records instructions of the given kinds, see [`Instruction::kind()`]
+    pub fn only_kinds(mut self, kinds: Vec<String>) -> Tracer<W> {
+        self.kinds = Some(kinds);
+        self
+    }
+
+    /// Flushes the records and returns the writer, or the first error that occurred while
+    /// writing them
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Observer for Tracer<W> {
+    fn instruction<M: MemoryBand>(&mut self, instruction: Instruction, span: Span, band: &M, before: u8) {
+        self.step += 1;
+        if let Some(kinds) = &self.kinds {
+            if !kinds.iter().any(|kind| kind == instruction.kind()) {
+                return;
+            }
+        }
+        self.matched += 1;
+        if self.error.is_some() || !self.matched.is_multiple_of(self.every) {
+            return;
+        }
+        let (line, column) = self.lines.line_col(span.start);
+        let result = writeln!(
+            self.out,
+            "{{\"step\":{},\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"op\":\"{:?}\",\"head\":{},\"before\":{},\"after\":{}}}",
+            self.step,
+            span.start,
+            span.end,
+            line,
+            column,
+            instruction,
+            band.position(),
+            before,
+            band.read()
+        );
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memoryband::InfiniteMemoryBand;
+    use crate::observer::run_observed;
+    use crate::sourcecode::SourceCode;
+
+    fn trace(source: &str, tracer: impl FnOnce(Tracer<Vec<u8>>) -> Tracer<Vec<u8>>) -> Vec<String> {
+        let code = source.parse::<SourceCode>().unwrap();
+        let mut tracer = tracer(Tracer::new(Vec::new(), source));
+        run_observed(&code, &mut InfiniteMemoryBand::new(), &[], &mut tracer);
+        let out = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        out.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_trace() {
+        let lines = trace("++\n[->+<]", |t| t);
+
+        assert_eq!(lines.len(), 2 + 2 * 5);
+        assert_eq!(lines[0], r#"{"step":1,"start":0,"end":2,"line":1,"column":1,"op":"Add(2)","head":0,"before":0,"after":2}"#);
+        assert_eq!(lines[1], r#"{"step":2,"start":3,"end":4,"line":2,"column":1,"op":"LoopStart","head":0,"before":2,"after":2}"#);
+        assert_eq!(lines[3], r#"{"step":4,"start":5,"end":6,"line":2,"column":3,"op":"Move(1)","head":1,"before":1,"after":0}"#);
+        assert!(lines[11].contains(r#""op":"LoopEnd","head":0,"before":0,"after":0"#));
+    }
+
+    #[test]
+    fn test_trace_filter() {
+        let lines = trace("+++[-]", |t| t.only_kinds(vec![String::from("loop")]));
+        assert_eq!(lines.len(), 4);
+
+        let lines = trace("+++[-]", |t| t.sample_every(3));
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(r#"{"step":3,"#));
+
+        // every second of the four loop instructions
+        let lines = trace("+++[-]", |t| t.only_kinds(vec![String::from("loop")]).sample_every(2));
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(r#"{"step":4,"#));
+        assert!(lines[1].contains(r#""op":"LoopEnd","head":0,"before":0"#));
+    }
+}
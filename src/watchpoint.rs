@@ -0,0 +1,159 @@
+use super::observer::Observer;
+use std::fmt;
+
+/// A condition on the memoryband that stops a [`super::debugger::Debugger`].
+///
+/// Cells are given by their position relative to the cell the head started on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// The cell is written, even if its value stays the same
+    Written(isize),
+    /// The value of the cell changes
+    Changed(isize),
+    /// The value of the cell changes to the given value
+    Reaches(isize, u8),
+    /// The head moves left of the given position
+    HeadLeftOf(isize),
+    /// The head moves right of the given position
+    HeadRightOf(isize),
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watchpoint::Written(cell) => write!(f, "write to cell {}", cell),
+            Watchpoint::Changed(cell) => write!(f, "change of cell {}", cell),
+            Watchpoint::Reaches(cell, value) => write!(f, "cell {} reaching {}", cell, value),
+            Watchpoint::HeadLeftOf(position) => write!(f, "head moving left of {}", position),
+            Watchpoint::HeadRightOf(position) => write!(f, "head moving right of {}", position),
+        }
+    }
+}
+
+/// An [`Observer`] that checks a list of [`Watchpoint`]s on every write and head movement and
+/// remembers the first one that triggered.
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    /// Index of the triggered watchpoint and what happened
+    hit: Option<(usize, String)>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Watchpoints {
+        Watchpoints::default()
+    }
+
+    /// Adds `watchpoint`, returns its index
+    pub fn add(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    /// Removes the watchpoint at `index`, returns it if there was one
+    pub fn remove(&mut self, index: usize) -> Option<Watchpoint> {
+        if index < self.watchpoints.len() {
+            Some(self.watchpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Whether a watchpoint triggered since the last call of [`Watchpoints::take_hit()`]
+    pub fn is_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    /// Returns and forgets the index of the triggered watchpoint and a description of what
+    /// happened
+    pub fn take_hit(&mut self) -> Option<(usize, String)> {
+        self.hit.take()
+    }
+
+    /// Remembers the first watchpoint `trigger` returns a description for, unless one already
+    /// triggered
+    fn check<F>(&mut self, trigger: F)
+    where F: Fn(&Watchpoint) -> Option<String> {
+        if self.hit.is_none() {
+            self.hit = self
+                .watchpoints
+                .iter()
+                .enumerate()
+                .find_map(|(i, watchpoint)| trigger(watchpoint).map(|description| (i, description)));
+        }
+    }
+}
+
+impl Observer for Watchpoints {
+    fn cell_written(&mut self, position: isize, before: u8, after: u8) {
+        self.check(|watchpoint| match *watchpoint {
+            Watchpoint::Written(cell) if cell == position => {
+                Some(format!("cell {} written with {}", cell, after))
+            }
+            Watchpoint::Changed(cell) if cell == position && before != after => {
+                Some(format!("cell {} changed from {} to {}", cell, before, after))
+            }
+            Watchpoint::Reaches(cell, value) if cell == position && before != after && after == value => {
+                Some(format!("cell {} changed from {} to {}", cell, before, after))
+            }
+            _ => None,
+        });
+    }
+
+    fn head_moved(&mut self, from: isize, to: isize) {
+        self.check(|watchpoint| match *watchpoint {
+            Watchpoint::HeadLeftOf(boundary) if from >= boundary && to < boundary => {
+                Some(format!("head moved from {} to {}", from, to))
+            }
+            Watchpoint::HeadRightOf(boundary) if from <= boundary && to > boundary => {
+                Some(format!("head moved from {} to {}", from, to))
+            }
+            _ => None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cells() {
+        let mut watchpoints = Watchpoints::new();
+        watchpoints.add(Watchpoint::Changed(1));
+        watchpoints.add(Watchpoint::Reaches(2, 5));
+        watchpoints.add(Watchpoint::Written(2));
+
+        watchpoints.cell_written(1, 3, 3);
+        watchpoints.cell_written(0, 3, 4);
+        assert!(!watchpoints.is_hit());
+
+        watchpoints.cell_written(2, 4, 5);
+        watchpoints.cell_written(1, 3, 4);
+        assert_eq!(watchpoints.take_hit(), Some((1, String::from("cell 2 changed from 4 to 5"))));
+        assert_eq!(watchpoints.take_hit(), None);
+
+        watchpoints.cell_written(2, 5, 5);
+        assert_eq!(watchpoints.take_hit(), Some((2, String::from("cell 2 written with 5"))));
+    }
+
+    #[test]
+    fn test_head() {
+        let mut watchpoints = Watchpoints::new();
+        watchpoints.add(Watchpoint::HeadLeftOf(0));
+        watchpoints.add(Watchpoint::HeadRightOf(3));
+
+        watchpoints.head_moved(0, 3);
+        watchpoints.head_moved(-2, -3);
+        assert!(!watchpoints.is_hit());
+
+        watchpoints.head_moved(3, 5);
+        assert_eq!(watchpoints.take_hit(), Some((1, String::from("head moved from 3 to 5"))));
+        assert_eq!(watchpoints.remove(0), Some(Watchpoint::HeadLeftOf(0)));
+        assert_eq!(watchpoints.watchpoints(), &[Watchpoint::HeadRightOf(3)]);
+    }
+}